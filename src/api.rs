@@ -1,115 +1,301 @@
-use std::{error::Error, io::Stdout};
+use std::{
+    cell::Cell,
+    thread,
+    time::{Duration, Instant},
+};
 
-use quick_xml::{de::from_str, events::Event, Reader};
-use ratatui::{backend::CrosstermBackend, Terminal};
-use reqwest::blocking::Client;
+use reqwest::{
+    blocking::{Client, RequestBuilder, Response},
+    header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use scraper::{ElementRef, Html};
 
 use crate::{
-    overview::{ThreadOverview, XMLDiscussion},
-    thread::{ThreadComment, ThreadData, XMLComment},
-    ui::print_info,
+    cache::{CachedPage, PageCache},
+    config::{Config, Network, Ttl},
+    error::Error,
+    html::elements_with_attr_value,
+    overview::{Category, Source, ThreadOverview},
+    thread::{ThreadComment, ThreadData},
 };
 
-pub fn get_threads(
-    client: &Client,
-    page: u16,
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    draw: bool,
-) -> Result<Vec<ThreadOverview>, Box<dyn Error>> {
-    let url = format!("https://thephilosophyforum.com/discussions/p{}", page);
-    if draw {
-        print_info(terminal, "Fetching threads overviews...")?;
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+pub struct ForumClient {
+    http: Client,
+    base_url: String,
+    cache: PageCache,
+    offline: bool,
+    ttl: Ttl,
+    network: Network,
+    last_request: Cell<Option<Instant>>,
+}
+
+impl ForumClient {
+    pub fn new(config: &Config) -> Result<Self, Error> {
+        let http = Client::builder()
+            .timeout(Duration::from_secs(config.network.timeout))
+            .build()?;
+        return Ok(ForumClient {
+            http,
+            base_url: config.base_url.clone(),
+            cache: PageCache::new(config.paths.pages()),
+            offline: config.offline,
+            ttl: config.ttl,
+            network: config.network,
+            last_request: Cell::new(None),
+        });
     }
-    let body = client.get(url).send()?.text()?;
-    let mut reader = Reader::from_str(body.as_str());
-    reader.trim_text(true);
-
-    let mut result: Vec<ThreadOverview> = Vec::new();
-
-    loop {
-        match reader.read_event() {
-            Err(e) => return Err(Box::new(e)),
-            Ok(Event::Eof) => break,
-            Ok(Event::Start(tag)) => {
-                if tag.attributes().map(|a| a.unwrap().value).any(|att| {
-                    let blob = att.as_ref();
-                    let attribute = std::str::from_utf8(blob).unwrap();
-                    attribute == "Item"
-                }) {
-                    let vv = reader.read_text(tag.to_end().name())?;
-                    let mut thread_text = String::new();
-                    thread_text.push_str("<html>");
-                    thread_text.push_str(vv.as_ref());
-                    thread_text.push_str("</html>");
-
-                    let mut url = String::from("https://thephilosophyforum.com/");
-                    let discussion: XMLDiscussion = from_str(&thread_text).unwrap();
-                    url.push_str(discussion.title.value.href.as_str());
-
-                    result.push(ThreadOverview {
-                        title: discussion.title.value.title,
-                        url,
-                        author: discussion.author.name,
-                        replies: discussion.replies.replies,
+
+    /// Waits until enough time has passed since the previous request, every
+    /// request goes through the same client so this limits the whole app.
+    fn throttle(&self) {
+        let interval = Duration::from_millis(self.network.request_interval);
+        if let Some(last) = self.last_request.get() {
+            let elapsed = last.elapsed();
+            if elapsed < interval {
+                thread::sleep(interval - elapsed);
+            }
+        }
+        self.last_request.set(Some(Instant::now()));
+    }
+
+    /// Sends the request built by `build`, retrying transient failures with
+    /// an exponential backoff.
+    fn send(&self, url: &str, build: impl Fn() -> RequestBuilder) -> Result<Response, Error> {
+        let mut attempt = 0;
+        loop {
+            self.throttle();
+            let result =
+                build()
+                    .send()
+                    .map_err(Error::from)
+                    .and_then(|response| match response.status() {
+                        status
+                            if status.is_server_error()
+                                || status == StatusCode::TOO_MANY_REQUESTS =>
+                        {
+                            Err(Error::Status {
+                                url: url.to_string(),
+                                status,
+                            })
+                        }
+                        _ => Ok(response),
                     });
+            match result {
+                Err(e) if e.is_transient() && attempt < self.network.retries => {
+                    let backoff = self.network.backoff.saturating_mul(1 << attempt.min(16));
+                    thread::sleep(Duration::from_millis(backoff).min(MAX_BACKOFF));
+                    attempt += 1;
                 }
+                result => return result,
             }
-            _ => (),
+        }
+    }
+
+    /// Full URL of a forum resource, `path` may also be a URL pointing at
+    /// another host, in which case only its path is kept.
+    pub fn url(&self, path: &str) -> String {
+        let mut url = self.base_url.clone();
+        url.push_str(resource_path(path).as_str());
+        return url;
+    }
+
+    /// Returns the body of the page along with the time it was fetched at
+    /// when it comes from the offline cache. Cached pages younger than `ttl`
+    /// seconds are used as is, older ones are revalidated with the forum.
+    fn get(&self, path: &str, ttl: u64) -> Result<(String, Option<u64>), Error> {
+        let key = resource_path(path);
+        let cached = self.cache.get(key.as_str());
+        if self.offline {
+            return match cached {
+                Some(page) => Ok((page.body, Some(page.fetched_at))),
+                None => Err(Error::NotCached(key)),
+            };
+        }
+        if let Some(page) = cached.as_ref().filter(|p| p.age() < ttl) {
+            return Ok((page.body.clone(), None));
+        }
+
+        let url = self.url(path);
+        let response = self.send(url.as_str(), || {
+            let mut request = self.http.get(url.as_str());
+            if let Some(page) = cached.as_ref() {
+                if let Some(etag) = page.etag.as_ref() {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = page.last_modified.as_ref() {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            request
+        })?;
+
+        let page = match (response.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(page)) => {
+                CachedPage::new(page.body, page.etag, page.last_modified)
+            }
+            (status, _) if !status.is_success() => return Err(Error::Status { url, status }),
+            _ => {
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|v: &HeaderValue| v.to_str().ok())
+                        .map(|v| v.to_string())
+                };
+                let etag = header(ETAG);
+                let last_modified = header(LAST_MODIFIED);
+                CachedPage::new(response.text()?, etag, last_modified)
+            }
+        };
+        // The cache is best effort, browsing goes on without it
+        let _ = self.cache.put(key.as_str(), &page);
+        return Ok((page.body, None));
+    }
+}
+
+/// Strips the scheme, host and leading slashes so that the result can be
+/// appended to any base URL.
+pub fn resource_path(href: &str) -> String {
+    let path = match href.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|i| &rest[i..]).unwrap_or(""),
+        None => href,
+    };
+    return path.trim_start_matches('/').to_string();
+}
+
+/// Percent-encodes every byte but ASCII alphanumerics and those in `keep`.
+pub fn percent_encode(text: &str, keep: &[u8]) -> String {
+    return text.bytes().fold(String::new(), |mut acc, b| {
+        if b.is_ascii_alphanumeric() || keep.contains(&b) {
+            acc.push(b as char);
+        } else {
+            acc.push_str(format!("%{:02X}", b).as_str());
+        }
+        acc
+    });
+}
+
+pub fn get_threads(
+    client: &ForumClient,
+    source: &Source,
+    page: u16,
+) -> Result<(Vec<ThreadOverview>, Option<u64>), Error> {
+    let path = match source {
+        Source::Discussions => format!("discussions/p{}", page),
+        Source::Category(c) => format!("{}/p{}", c.url, page),
+        Source::Search(query) => {
+            format!("search/p{}?Search={}", page, percent_encode(query, b"-_.~"))
+        }
+        // Filled from the index, never fetched
+        Source::Index(_) => return Ok((vec![], None)),
+    };
+    let (body, cached_at) = client.get(path.as_str(), client.ttl.overview)?;
+    let document = Html::parse_document(body.as_str());
+    let result = match source {
+        Source::Search(_) => search_results(&document),
+        _ => elements_with_attr_value(&document, "Item")
+            .filter_map(ThreadOverview::from_html)
+            .collect(),
+    };
+    return Ok((result, cached_at));
+}
+
+/// Results link to discussions as well as to comments within them, each
+/// discussion is kept once.
+fn search_results(document: &Html) -> Vec<ThreadOverview> {
+    let mut result: Vec<ThreadOverview> = vec![];
+    let threads = document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|e| e.value().name() == "a")
+        .filter_map(ThreadOverview::from_search_link);
+    for thread in threads {
+        if !result.iter().any(|t| t.url == thread.url) {
+            result.push(thread);
+        }
+    }
+    return result;
+}
+
+pub fn get_categories(client: &ForumClient) -> Result<Vec<Category>, Error> {
+    let (body, _) = client.get("categories", client.ttl.overview)?;
+    let document = Html::parse_document(body.as_str());
+    let mut result: Vec<Category> = vec![];
+    let links = document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(|e| e.value().name() == "a")
+        .filter_map(Category::from_link);
+    // Categories tend to be linked more than once, e.g. from the sidebar
+    for category in links {
+        if !result.iter().any(|c| c.url == category.url) {
+            result.push(category);
         }
     }
     return Ok(result);
 }
 
 pub fn get_thread(
-    client: &Client,
+    client: &ForumClient,
     thread: &ThreadOverview,
     page: u16,
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    draw: bool,
-) -> Result<ThreadData, Box<dyn Error>> {
-    let mut url = thread.url.clone();
-    url.push_str(format!("/p{}", page).as_str());
-    if draw {
-        print_info(terminal, "Fetching thread data...")?;
-    }
-    let body = client.get(url).send()?.text()?;
+    revalidate: bool,
+) -> Result<ThreadData, Error> {
     let mut result = ThreadData {
         title: thread.title.clone(),
         ..Default::default()
     };
-    let mut reader = Reader::from_str(body.as_str());
-    reader.trim_text(true);
-
-    loop {
-        match reader.read_event() {
-            Err(e) => return Err(Box::new(e)),
-            Ok(Event::Eof) => break,
-            Ok(Event::Start(tag)) => {
-                if tag.attributes().map(|a| a.unwrap().value).any(|att| {
-                    let blob = att.as_ref();
-                    let attribute = std::str::from_utf8(blob).unwrap();
-                    attribute == "Comment"
-                }) {
-                    let t_data = reader
-                        .read_text(tag.to_end().name())?
-                        .replace("&mdash;", "-");
-                    let mut comment_text = String::new();
-                    comment_text.push_str("<html>");
-                    comment_text.push_str(t_data.as_ref());
-                    comment_text.push_str("</html>");
-
-                    let comment: XMLComment = from_str(&comment_text)?;
-                    let ris = ThreadComment {
-                        author: comment.author.name.value,
-                        text: comment.text.text,
-                        date: comment.date.value.value.value.value,
-                    };
-                    result.comments.push(ris);
-                }
-            }
-            _ => (),
+    // Once a following page exists a page no longer changes much
+    let ttl = if revalidate {
+        0
+    } else if has_next_page(client, thread, page) {
+        client.ttl.old_thread
+    } else {
+        client.ttl.thread
+    };
+    let body = match client.get(format!("{}/p{}", thread.url, page).as_str(), ttl) {
+        Ok((body, cached_at)) => {
+            result.cached_at = cached_at;
+            body
         }
-    }
+        // Pages past the last one don't exist
+        Err(Error::Status { status, .. }) if status == StatusCode::NOT_FOUND && page > 1 => {
+            return Ok(result);
+        }
+        Err(Error::NotCached(_)) if page > 1 => return Ok(result),
+        Err(e) => return Err(e),
+    };
+    let document = Html::parse_document(body.as_str());
+    result.comments = elements_with_attr_value(&document, "Comment")
+        .map(ThreadComment::from_html)
+        .collect();
 
     return Ok(result);
 }
+
+/// Whether the cache holds a page following `page`. The forum answers past
+/// the last page with the last page again, so the following page only counts
+/// when it has comments of its own.
+fn has_next_page(client: &ForumClient, thread: &ThreadOverview, page: u16) -> bool {
+    let comments = |page: u16| {
+        let cached = client
+            .cache
+            .get(resource_path(format!("{}/p{}", thread.url, page).as_str()).as_str())?;
+        let document = Html::parse_document(cached.body.as_str());
+        return Some(
+            elements_with_attr_value(&document, "Comment")
+                .map(ThreadComment::from_html)
+                .map(|c| (c.author, c.date))
+                .collect::<Vec<_>>(),
+        );
+    };
+    return match (comments(page), comments(page + 1)) {
+        (Some(current), Some(next)) => next.iter().any(|c| !current.contains(c)),
+        _ => false,
+    };
+}
@@ -0,0 +1,472 @@
+use std::{fmt::Display, mem};
+
+use crate::{model::ThreadList, overview::ThreadOverview, thread::ThreadData};
+
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub thread: ThreadOverview,
+    pub folder: Option<String>,
+    pub tags: Vec<String>,
+    /// Comments in the thread when it was last entirely loaded, `None` if
+    /// it never was
+    pub comments: Option<u32>,
+    /// Value of `comments` when the bookmark was last opened
+    pub seen: u32,
+    /// Where the user left the thread, only up to date while it's hidden
+    /// by the filter, see [`Bookmarks::place`]
+    pub selected_comment: u16,
+    pub viewer_scroll: u16,
+}
+
+impl Bookmark {
+    pub fn new(thread: ThreadOverview) -> Self {
+        Bookmark {
+            thread,
+            folder: None,
+            tags: vec![],
+            comments: None,
+            seen: 0,
+            selected_comment: 0,
+            viewer_scroll: 0,
+        }
+    }
+
+    /// Comments added since the bookmark was last opened.
+    pub fn new_comments(&self) -> u32 {
+        return self.comments.map_or(0, |c| c.saturating_sub(self.seen));
+    }
+}
+
+/// Restricts the bookmarks tab to a folder or a tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Folder(String),
+    Tag(String),
+}
+
+impl Filter {
+    /// `#name` selects a tag and anything else a folder, empty input means no
+    /// filter.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+        return match input.strip_prefix('#') {
+            Some(tag) => Some(Filter::Tag(tag.to_string())),
+            None => Some(Filter::Folder(input.to_string())),
+        };
+    }
+
+    fn matches(&self, bookmark: &Bookmark) -> bool {
+        return match self {
+            Filter::Folder(f) => bookmark.folder.as_ref() == Some(f),
+            Filter::Tag(t) => bookmark.tags.contains(t),
+        };
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Filter::Folder(folder) => write!(f, "{}", folder),
+            Filter::Tag(tag) => write!(f, "#{}", tag),
+        }
+    }
+}
+
+/// Threads saved by the user, in the order they chose.
+#[derive(Default)]
+pub struct Bookmarks {
+    /// Bookmarks matching the filter, as shown in the tab
+    pub list: ThreadList,
+    entries: Vec<Bookmark>,
+    pub filter: Option<Filter>,
+    /// Threads fetched up to their last page by a refresh
+    refreshing: Vec<String>,
+    /// Changed since it was last written to disk
+    pub unsaved: bool,
+}
+
+impl Bookmarks {
+    /// Threads are loaded when selected, from the page cache if possible.
+    pub fn new(entries: Vec<Bookmark>) -> Self {
+        let mut bookmarks = Bookmarks {
+            entries,
+            ..Default::default()
+        };
+        bookmarks.rebuild();
+        return bookmarks;
+    }
+
+    pub fn entries(&self) -> &[Bookmark] {
+        return &self.entries;
+    }
+
+    pub fn get(&self, url: &str) -> Option<&Bookmark> {
+        return self.entries.iter().find(|b| b.thread.url == url);
+    }
+
+    fn get_mut(&mut self, url: &str) -> Option<&mut Bookmark> {
+        return self.entries.iter_mut().find(|b| b.thread.url == url);
+    }
+
+    /// Selected comment and scroll offset of a bookmark.
+    pub fn place(&self, bookmark: &Bookmark) -> (u16, u16) {
+        let url = bookmark.thread.url.as_str();
+        return match self.list.overview.iter().position(|t| t.url == url) {
+            Some(n) => {
+                let data = &self.list.threads[n];
+                (data.selected_comment, data.viewer_scroll)
+            }
+            None => (bookmark.selected_comment, bookmark.viewer_scroll),
+        };
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        return self.get(url).is_some();
+    }
+
+    /// Returns whether the thread wasn't already bookmarked.
+    pub fn add(&mut self, over: ThreadOverview, data: ThreadData) -> bool {
+        if self.contains(over.url.as_str()) {
+            return false;
+        }
+        let mut bookmark = Bookmark::new(over.clone());
+        if data.complete {
+            bookmark.comments = Some(data.comments.len() as u32);
+            bookmark.seen = data.comments.len() as u32;
+        }
+        if self.filter.as_ref().is_none_or(|f| f.matches(&bookmark)) {
+            self.list.push(over, data);
+        }
+        self.entries.push(bookmark);
+        self.unsaved = true;
+        return true;
+    }
+
+    pub fn remove(&mut self, url: &str) -> bool {
+        let Some(n) = self.entries.iter().position(|b| b.thread.url == url) else {
+            return false;
+        };
+        self.entries.remove(n);
+        if let Some(n) = self.list.overview.iter().position(|t| t.url == url) {
+            self.list.remove(n);
+        }
+        self.unsaved = true;
+        return true;
+    }
+
+    /// Moves the selected bookmark one place up or down among the shown ones,
+    /// it stays selected.
+    pub fn move_selected(&mut self, up: bool) -> bool {
+        let selected = self.list.selected_thread as usize;
+        let target = if up {
+            selected.checked_sub(1)
+        } else {
+            Some(selected + 1).filter(|&n| n < self.list.overview.len())
+        };
+        let Some(target) = target else {
+            return false;
+        };
+
+        let position = |url: &str| self.entries.iter().position(|b| b.thread.url == url);
+        let a = position(self.list.overview[selected].url.as_str());
+        let b = position(self.list.overview[target].url.as_str());
+        if let (Some(a), Some(b)) = (a, b) {
+            self.entries.swap(a, b);
+        }
+        self.list.swap(selected, target);
+        self.unsaved = true;
+        return true;
+    }
+
+    /// Marks the shown bookmarks as being refreshed, returns the page each
+    /// of them has to be fetched from.
+    pub fn refresh(&mut self) -> Vec<(ThreadOverview, u16)> {
+        let mut requests = vec![];
+        for (over, data) in self.list.overview.iter().zip(self.list.threads.iter_mut()) {
+            data.complete = false;
+            if !self.refreshing.contains(&over.url) {
+                self.refreshing.push(over.url.clone());
+            }
+            requests.push((over.clone(), data.comment_page.max(1)));
+        }
+        return requests;
+    }
+
+    pub fn is_refreshing(&self, url: &str) -> bool {
+        return self.refreshing.iter().any(|u| u == url);
+    }
+
+    pub fn stop_refreshing(&mut self, url: &str) {
+        self.refreshing.retain(|u| u != url);
+    }
+
+    pub fn cancel_refresh(&mut self) {
+        self.refreshing.clear();
+    }
+
+    /// Records the number of comments of a completely loaded thread. The
+    /// first count of a bookmark is taken as already seen.
+    pub fn set_count(&mut self, url: &str, count: u32) {
+        self.stop_refreshing(url);
+        let Some(bookmark) = self.get_mut(url) else {
+            return;
+        };
+        if bookmark.comments.is_none() {
+            bookmark.seen = count;
+        }
+        if bookmark.comments != Some(count) {
+            bookmark.comments = Some(count);
+            self.unsaved = true;
+        }
+    }
+
+    pub fn mark_seen(&mut self, url: &str) {
+        let Some(bookmark) = self.get_mut(url) else {
+            return;
+        };
+        if let Some(count) = bookmark.comments.filter(|&c| c != bookmark.seen) {
+            bookmark.seen = count;
+            self.unsaved = true;
+        }
+    }
+
+    pub fn set_folder(&mut self, url: &str, folder: Option<String>) {
+        let Some(bookmark) = self.get_mut(url) else {
+            return;
+        };
+        bookmark.folder = folder;
+        self.unsaved = true;
+        self.rebuild();
+    }
+
+    pub fn set_tags(&mut self, url: &str, tags: Vec<String>) {
+        let Some(bookmark) = self.get_mut(url) else {
+            return;
+        };
+        bookmark.tags = tags;
+        self.unsaved = true;
+        self.rebuild();
+    }
+
+    pub fn set_filter(&mut self, filter: Option<Filter>) {
+        self.filter = filter;
+        self.rebuild();
+    }
+
+    /// Fills the list with the bookmarks matching the filter, keeping what
+    /// was already loaded and the selection when it's still shown.
+    fn rebuild(&mut self) {
+        let mut old = mem::take(&mut self.list);
+        let selected = old.selected().map(|t| t.url.clone());
+        self.list.filter = old.filter.take();
+        self.list.sort = old.sort;
+        for (over, data) in old.overview.iter().zip(old.threads.iter()) {
+            if let Some(b) = self.entries.iter_mut().find(|b| b.thread.url == over.url) {
+                b.selected_comment = data.selected_comment;
+                b.viewer_scroll = data.viewer_scroll;
+            }
+        }
+
+        for bookmark in self.entries.iter() {
+            if self.filter.as_ref().is_some_and(|f| !f.matches(bookmark)) {
+                continue;
+            }
+            let over = &bookmark.thread;
+            let data = match old.overview.iter().position(|t| t.url == over.url) {
+                Some(n) => mem::take(&mut old.threads[n]),
+                None => placeholder(bookmark),
+            };
+            self.list.push(over.clone(), data);
+        }
+
+        let position =
+            selected.and_then(|url| self.list.overview.iter().position(|t| t.url == url));
+        if let Some(n) = position {
+            self.list.selected_thread = n as u16;
+        }
+    }
+}
+
+/// Data of a bookmark that hasn't been loaded yet, positioned where the user
+/// left it.
+fn placeholder(bookmark: &Bookmark) -> ThreadData {
+    return ThreadData {
+        title: bookmark.thread.title.clone(),
+        selected_comment: bookmark.selected_comment,
+        viewer_scroll: bookmark.viewer_scroll,
+        ..Default::default()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thread(url: &str) -> ThreadOverview {
+        return ThreadOverview {
+            title: url.to_uppercase(),
+            url: String::from(url),
+            author: String::new(),
+            replies: None,
+            category: None,
+            last_activity: None,
+        };
+    }
+
+    fn bookmarks(urls: &[&str]) -> Bookmarks {
+        return Bookmarks::new(urls.iter().map(|u| Bookmark::new(thread(u))).collect());
+    }
+
+    fn urls(bookmarks: &Bookmarks) -> Vec<&str> {
+        return bookmarks
+            .entries()
+            .iter()
+            .map(|b| b.thread.url.as_str())
+            .collect();
+    }
+
+    fn selected(bookmarks: &Bookmarks) -> Option<&str> {
+        return bookmarks.list.selected().map(|t| t.url.as_str());
+    }
+
+    #[test]
+    fn add_ignores_a_bookmarked_url() {
+        let mut bookmarks = bookmarks(&["a"]);
+        assert!(!bookmarks.add(thread("a"), ThreadData::default()));
+        assert!(!bookmarks.unsaved);
+        assert!(bookmarks.add(thread("b"), ThreadData::default()));
+        assert!(bookmarks.unsaved);
+        assert_eq!(urls(&bookmarks), ["a", "b"]);
+        assert_eq!(bookmarks.list.overview.len(), 2);
+    }
+
+    #[test]
+    fn remove_keeps_the_selection_on_the_same_thread() {
+        let mut bookmarks = bookmarks(&["a", "b", "c"]);
+        bookmarks.list.selected_thread = 2;
+        assert!(bookmarks.remove("a"));
+        assert_eq!(selected(&bookmarks), Some("c"));
+        assert!(!bookmarks.remove("a"));
+        // Removing the last thread selects the one before it
+        assert!(bookmarks.remove("c"));
+        assert_eq!(selected(&bookmarks), Some("b"));
+        assert_eq!(urls(&bookmarks), ["b"]);
+    }
+
+    #[test]
+    fn move_selected_stops_at_both_ends() {
+        let mut bookmarks = bookmarks(&["a", "b", "c"]);
+        assert!(!bookmarks.move_selected(true));
+        assert!(bookmarks.move_selected(false));
+        assert_eq!(urls(&bookmarks), ["b", "a", "c"]);
+        assert_eq!(selected(&bookmarks), Some("a"));
+
+        bookmarks.list.selected_thread = 2;
+        bookmarks.unsaved = false;
+        assert!(!bookmarks.move_selected(false));
+        assert!(!bookmarks.unsaved);
+        assert!(bookmarks.move_selected(true));
+        assert_eq!(urls(&bookmarks), ["b", "c", "a"]);
+        assert_eq!(selected(&bookmarks), Some("c"));
+    }
+
+    fn shown(bookmarks: &Bookmarks) -> Vec<&str> {
+        return bookmarks
+            .list
+            .overview
+            .iter()
+            .map(|t| t.url.as_str())
+            .collect();
+    }
+
+    #[test]
+    fn filter_by_folder_or_tag_keeps_the_shown_selection() {
+        let mut bookmarks = bookmarks(&["a", "b", "c"]);
+        bookmarks.set_folder("a", Some(String::from("ethics")));
+        bookmarks.set_folder("c", Some(String::from("ethics")));
+        bookmarks.set_tags("b", vec![String::from("todo")]);
+        bookmarks.list.selected_thread = 2;
+        bookmarks.list.threads[2].comment_page = 1;
+
+        bookmarks.set_filter(Filter::parse("ethics"));
+        assert_eq!(shown(&bookmarks), ["a", "c"]);
+        assert_eq!(selected(&bookmarks), Some("c"));
+        assert_eq!(bookmarks.list.threads[1].comment_page, 1);
+
+        bookmarks.set_filter(Filter::parse("#todo"));
+        assert_eq!(shown(&bookmarks), ["b"]);
+        // The selection moves to the first shown bookmark when it's hidden
+        assert_eq!(selected(&bookmarks), Some("b"));
+
+        bookmarks.set_filter(None);
+        assert_eq!(shown(&bookmarks), ["a", "b", "c"]);
+        assert_eq!(selected(&bookmarks), Some("b"));
+    }
+
+    #[test]
+    fn changes_under_a_filter_apply_to_every_bookmark() {
+        let mut bookmarks = bookmarks(&["a", "b", "c"]);
+        bookmarks.set_tags("a", vec![String::from("todo")]);
+        bookmarks.set_tags("c", vec![String::from("todo")]);
+        bookmarks.set_filter(Filter::parse("#todo"));
+
+        // Added outside of the filter, so not shown
+        bookmarks.add(thread("d"), ThreadData::default());
+        assert_eq!(shown(&bookmarks), ["a", "c"]);
+
+        bookmarks.set_tags("c", vec![]);
+        assert_eq!(shown(&bookmarks), ["a"]);
+        bookmarks.set_tags("c", vec![String::from("todo")]);
+
+        // Shown neighbours swap places around the hidden bookmark
+        assert!(bookmarks.move_selected(false));
+        assert_eq!(shown(&bookmarks), ["c", "a"]);
+        assert_eq!(urls(&bookmarks), ["c", "b", "a", "d"]);
+    }
+
+    #[test]
+    fn first_count_is_taken_as_seen() {
+        let mut bookmarks = bookmarks(&["a"]);
+        bookmarks.set_count("a", 3);
+        let bookmark = bookmarks.get("a").unwrap();
+        assert_eq!((bookmark.comments, bookmark.seen), (Some(3), 3));
+        assert_eq!(bookmark.new_comments(), 0);
+        assert!(bookmarks.unsaved);
+    }
+
+    #[test]
+    fn new_comments_are_counted_until_seen() {
+        let mut bookmarks = bookmarks(&["a"]);
+        bookmarks.set_count("a", 3);
+        bookmarks.set_count("a", 5);
+        assert_eq!(bookmarks.get("a").unwrap().new_comments(), 2);
+
+        bookmarks.unsaved = false;
+        bookmarks.set_count("a", 5);
+        assert!(!bookmarks.unsaved);
+
+        bookmarks.mark_seen("a");
+        assert_eq!(bookmarks.get("a").unwrap().new_comments(), 0);
+        assert!(bookmarks.unsaved);
+    }
+
+    #[test]
+    fn never_counted_bookmarks_have_no_new_comments() {
+        let mut bookmarks = bookmarks(&["a"]);
+        bookmarks.mark_seen("a");
+        assert_eq!(bookmarks.get("a").unwrap().new_comments(), 0);
+        assert!(!bookmarks.unsaved);
+    }
+
+    #[test]
+    fn set_count_ends_the_refresh_of_a_thread() {
+        let mut bookmarks = bookmarks(&["a", "b"]);
+        assert_eq!(bookmarks.refresh().len(), 2);
+        bookmarks.set_count("a", 1);
+        assert!(!bookmarks.is_refreshing("a"));
+        assert!(bookmarks.is_refreshing("b"));
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::percent_encode, error::Error};
+
+/// Longest file name most filesystems accept
+const MAX_NAME: usize = 255;
+
+/// A forum page as it was downloaded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedPage {
+    pub body: String,
+    /// Seconds since the unix epoch
+    pub fetched_at: u64,
+    #[serde(default)]
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+}
+
+impl CachedPage {
+    pub fn new(body: String, etag: Option<String>, last_modified: Option<String>) -> Self {
+        CachedPage {
+            body,
+            fetched_at: now(),
+            etag,
+            last_modified,
+        }
+    }
+
+    pub fn age(&self) -> u64 {
+        return now().saturating_sub(self.fetched_at);
+    }
+}
+
+/// On-disk store of fetched pages, keyed by their path relative to the
+/// forum root so that mirrors share the same entries.
+pub struct PageCache {
+    dir: PathBuf,
+}
+
+impl PageCache {
+    pub fn new(dir: PathBuf) -> Self {
+        PageCache { dir }
+    }
+
+    /// Keys are URL paths, `%` is escaped too so that `a/b` and `a%2Fb` get
+    /// names of their own. Names too long for the filesystem are shortened
+    /// and suffixed with a hash of the key.
+    fn entry_path(&self, key: &str) -> PathBuf {
+        let mut name = percent_encode(key, b"-.");
+        if name.len() > MAX_NAME {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            name.truncate(MAX_NAME - 17);
+            name.push_str(format!("~{:016x}", hasher.finish()).as_str());
+        }
+        return self.dir.join(name);
+    }
+
+    pub fn get(&self, key: &str) -> Option<CachedPage> {
+        let file = File::open(self.entry_path(key)).ok()?;
+        return serde_cbor::from_reader(file).ok();
+    }
+
+    pub fn put(&self, key: &str, page: &CachedPage) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)?;
+        let file = File::create(self.entry_path(key))?;
+        serde_cbor::to_writer(file, page)?;
+        return Ok(());
+    }
+}
+
+pub fn now() -> u64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_names_are_distinct_and_bounded() {
+        let cache = PageCache::new(PathBuf::from("pages"));
+        let name = |key: &str| cache.entry_path(key).file_name().unwrap().to_owned();
+        assert_ne!(name("discussion/1/a/b"), name("discussion/1/a%2Fb"));
+
+        let long = "x/".repeat(200);
+        assert!(name(long.as_str()).len() <= MAX_NAME);
+        assert_ne!(name(long.as_str()), name(format!("{}y", long).as_str()));
+    }
+}
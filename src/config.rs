@@ -0,0 +1,168 @@
+use std::{env, error::Error, fs, path::PathBuf};
+
+use crate::paths::Paths;
+
+pub const DEFAULT_BASE_URL: &str = "https://thephilosophyforum.com/";
+const BASE_URL_VAR: &str = "OXI_PHIL_BASE_URL";
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub base_url: String,
+    pub prefetch: Prefetch,
+    /// Serve every page from the cache instead of the network
+    pub offline: bool,
+    pub paths: Paths,
+    pub ttl: Ttl,
+    pub network: Network,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: String::from(DEFAULT_BASE_URL),
+            prefetch: Prefetch::default(),
+            offline: false,
+            paths: Paths::default(),
+            ttl: Ttl::default(),
+            network: Network::default(),
+        }
+    }
+}
+
+/// How far ahead of the selection content gets loaded, 0 disables.
+#[derive(Debug, Clone, Copy)]
+pub struct Prefetch {
+    /// Remaining loaded comments below which the next page is requested
+    pub comments: u16,
+    /// Threads below the selected one whose first page is preloaded
+    pub threads: u16,
+}
+
+impl Default for Prefetch {
+    fn default() -> Self {
+        Prefetch {
+            comments: 5,
+            threads: 1,
+        }
+    }
+}
+
+/// Seconds a cached page is used without asking the forum whether it changed.
+#[derive(Debug, Clone, Copy)]
+pub struct Ttl {
+    pub overview: u64,
+    /// Last known page of a thread, where new comments show up
+    pub thread: u64,
+    /// Pages of a thread followed by other pages
+    pub old_thread: u64,
+}
+
+impl Default for Ttl {
+    fn default() -> Self {
+        Ttl {
+            overview: 60,
+            thread: 5 * 60,
+            old_thread: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Network {
+    /// Seconds before a request is abandoned
+    pub timeout: u64,
+    /// Attempts made after a transient failure
+    pub retries: u32,
+    /// Milliseconds waited before the first retry, doubled at each attempt
+    pub backoff: u64,
+    /// Minimum milliseconds between two requests to the forum
+    pub request_interval: u64,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Network {
+            timeout: 15,
+            retries: 3,
+            backoff: 500,
+            request_interval: 1000,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from, in increasing order of priority, the
+    /// config file, the environment and the command line.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let mut config = Config {
+            paths: Paths::from_env()?,
+            ..Default::default()
+        };
+
+        if let Ok(text) = fs::read_to_string(config.paths.config_file()) {
+            config.apply_file(&text)?;
+        }
+
+        if let Ok(url) = env::var(BASE_URL_VAR) {
+            config.base_url = url;
+        }
+
+        config.apply_args(env::args().skip(1))?;
+        config.base_url = normalize_base_url(&config.base_url);
+        return Ok(config);
+    }
+
+    /// Reads `key = value` lines, `#` starts a comment.
+    fn apply_file(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("Invalid config line: {}", line))?;
+            match key.trim() {
+                "base_url" => self.base_url = value.trim().to_string(),
+                "prefetch_comments" => self.prefetch.comments = value.trim().parse()?,
+                "prefetch_threads" => self.prefetch.threads = value.trim().parse()?,
+                "offline" => self.offline = value.trim().parse()?,
+                "data_dir" => self.paths.data = PathBuf::from(value.trim()),
+                "ttl_overview" => self.ttl.overview = value.trim().parse()?,
+                "ttl_thread" => self.ttl.thread = value.trim().parse()?,
+                "ttl_old_thread" => self.ttl.old_thread = value.trim().parse()?,
+                "timeout" => self.network.timeout = value.trim().parse()?,
+                "retries" => self.network.retries = value.trim().parse()?,
+                "backoff" => self.network.backoff = value.trim().parse()?,
+                "request_interval" => self.network.request_interval = value.trim().parse()?,
+                k => return Err(format!("Unknown config key: {}", k).into()),
+            }
+        }
+        return Ok(());
+    }
+
+    fn apply_args(&mut self, mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn Error>> {
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--base-url" => {
+                    self.base_url = args.next().ok_or("--base-url requires a value")?;
+                }
+                "--offline" => self.offline = true,
+                "--data-dir" => {
+                    let dir = args.next().ok_or("--data-dir requires a value")?;
+                    self.paths.data = PathBuf::from(dir);
+                }
+                a => return Err(format!("Unknown argument: {}", a).into()),
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn normalize_base_url(url: &str) -> String {
+    let mut url = url.trim().to_string();
+    if !url.ends_with('/') {
+        url.push('/');
+    }
+    return url;
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+
+const MONTH: i64 = 30 * 86400;
+
+/// Reads the `datetime` attribute of the forum's `time` elements, which is
+/// in RFC 3339 or, without an offset, in UTC.
+pub fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    return NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|d| d.and_utc());
+}
+
+/// Short form of a duration in seconds, such as `3h`.
+pub fn age(seconds: u64) -> String {
+    return match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    };
+}
+
+/// How long ago `date` was, dates older than a month are shown as the local
+/// day instead.
+pub fn relative(date: DateTime<Utc>) -> String {
+    let seconds = (Utc::now() - date).num_seconds();
+    if seconds >= MONTH {
+        return date.with_timezone(&Local).format("%Y-%m-%d").to_string();
+    }
+    return format!("{} ago", age(seconds.max(0) as u64));
+}
+
+/// `date` in the local timezone.
+pub fn local(date: DateTime<Utc>) -> String {
+    return date
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone};
+
+    use super::*;
+
+    #[test]
+    fn parse_date_reads_rfc3339() {
+        let date = parse_date("2024-03-01T10:00:00+02:00");
+        assert_eq!(
+            date,
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 8, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_date_takes_dates_without_offset_as_utc() {
+        let date = parse_date(" 2024-03-01 10:00:00 ");
+        assert_eq!(
+            date,
+            Some(Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_date_rejects_other_formats() {
+        assert_eq!(parse_date("March 1, 2024"), None);
+        assert_eq!(parse_date(""), None);
+    }
+
+    #[test]
+    fn relative_shows_recent_dates_as_ages() {
+        assert_eq!(relative(Utc::now() - Duration::hours(2)), "2h ago");
+        assert_eq!(relative(Utc::now() - Duration::days(3)), "3d ago");
+    }
+
+    #[test]
+    fn relative_shows_old_dates_as_days() {
+        let date = Utc::now() - Duration::days(90);
+        let day = date.with_timezone(&Local).format("%Y-%m-%d").to_string();
+        assert_eq!(relative(date), day);
+    }
+
+    #[test]
+    fn relative_clamps_future_dates() {
+        assert_eq!(relative(Utc::now() + Duration::hours(1)), "0s ago");
+    }
+}
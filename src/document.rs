@@ -0,0 +1,544 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use scraper::{ElementRef, Node as HtmlNode};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Structured body of a comment.
+#[derive(Debug, Clone)]
+pub enum Node {
+    Text(String),
+    Break,
+    Paragraph(Vec<Node>),
+    Emphasis(Vec<Node>),
+    Strong(Vec<Node>),
+    Strikethrough(Vec<Node>),
+    Underline(Vec<Node>),
+    Superscript(Vec<Node>),
+    Link {
+        href: String,
+        children: Vec<Node>,
+    },
+    Quote {
+        author: Option<String>,
+        /// Link to the quoted comment
+        source: Option<String>,
+        children: Vec<Node>,
+    },
+    List {
+        ordered: bool,
+        items: Vec<Vec<Node>>,
+    },
+    Image {
+        src: String,
+        alt: String,
+    },
+    Embed {
+        src: String,
+    },
+}
+
+impl Node {
+    pub fn from_children(element: ElementRef) -> Vec<Node> {
+        return element.children().flat_map(Node::from_html).collect();
+    }
+
+    fn from_html(node: ego_tree::NodeRef<HtmlNode>) -> Vec<Node> {
+        let element = match (node.value(), ElementRef::wrap(node)) {
+            (HtmlNode::Text(t), _) => {
+                let text = collapse_whitespace(t);
+                if text.is_empty() {
+                    return vec![];
+                }
+                return vec![Node::Text(text)];
+            }
+            (_, Some(e)) => e,
+            _ => return vec![],
+        };
+
+        let attr = |name: &str| element.value().attr(name).unwrap_or_default().to_string();
+        let node = match element.value().name() {
+            "br" => Node::Break,
+            "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre" => {
+                Node::Paragraph(Node::from_children(element))
+            }
+            "i" | "em" => Node::Emphasis(Node::from_children(element)),
+            "b" | "strong" => Node::Strong(Node::from_children(element)),
+            "s" | "strike" | "del" => Node::Strikethrough(Node::from_children(element)),
+            "u" | "ins" => Node::Underline(Node::from_children(element)),
+            "sup" => Node::Superscript(Node::from_children(element)),
+            "a" => Node::Link {
+                href: attr("href"),
+                children: Node::from_children(element),
+            },
+            "blockquote" => quote_from_html(element),
+            "ul" | "ol" => Node::List {
+                ordered: element.value().name() == "ol",
+                items: element
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .map(Node::from_children)
+                    .collect(),
+            },
+            "img" => Node::Image {
+                src: attr("src"),
+                alt: attr("alt"),
+            },
+            "iframe" | "video" | "embed" | "object" => Node::Embed { src: attr("src") },
+            "script" | "style" | "noscript" => return vec![],
+            _ => return Node::from_children(element),
+        };
+        return vec![node];
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut ris = String::new();
+    let mut last_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                ris.push(' ');
+            }
+            last_space = true;
+        } else {
+            ris.push(c);
+            last_space = false;
+        }
+    }
+    if ris == " " {
+        ris.clear();
+    }
+    return ris;
+}
+
+/// The forum wraps quotes in a `div` holding the quoted text and a trailing
+/// `span` with a dash followed by a link to the author's comment. Anything
+/// not matching that shape is kept as quoted content.
+fn quote_from_html(element: ElementRef) -> Node {
+    let mut author = None;
+    let mut source = None;
+    let mut children = Vec::new();
+    let wrappers: Vec<ElementRef> = element.children().filter_map(ElementRef::wrap).collect();
+    let loose_text = element
+        .children()
+        .filter_map(|c| c.value().as_text())
+        .any(|t| !t.trim().is_empty());
+    let content = match wrappers.as_slice() {
+        [div] if div.value().name() == "div" && !loose_text => *div,
+        _ => element,
+    };
+
+    for child in content.children() {
+        match ElementRef::wrap(child) {
+            Some(e) if author.is_none() && is_attribution(e) => {
+                let link = e
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .find(|a| a.value().name() == "a");
+                author = link
+                    .map(|a| a.text().collect::<String>().trim().to_string())
+                    .filter(|a| !a.is_empty());
+                source = link
+                    .and_then(|a| a.value().attr("href"))
+                    .map(|href| href.to_string());
+            }
+            _ => children.append(&mut Node::from_html(child)),
+        }
+    }
+
+    return Node::Quote {
+        author,
+        source,
+        children,
+    };
+}
+
+fn is_attribution(element: ElementRef) -> bool {
+    let class = element.value().attr("class").unwrap_or_default();
+    if class.contains("Author") {
+        return true;
+    }
+    let leading: String = element
+        .children()
+        .take_while(|c| c.value().is_text())
+        .filter_map(|c| c.value().as_text().map(|t| t.to_string()))
+        .collect();
+    let has_link = element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .any(|a| a.value().name() == "a");
+    return has_link && matches!(leading.trim(), "\u{2014}" | "-" | "\u{2013}");
+}
+
+const QUOTE_PREFIX: &str = "\u{2502} ";
+
+/// Renders a document into styled lines no wider than `width`, blocks are
+/// separated by a single empty line and quotes are prefixed by a bar per
+/// nesting level.
+pub fn render(nodes: &[Node], width: u16) -> Vec<Line<'static>> {
+    let mut renderer = Renderer {
+        width: width as usize,
+        ..Default::default()
+    };
+    renderer.render_all(nodes, Style::default());
+    renderer.end_line();
+    renderer.trim_blank();
+    return renderer.lines.into_iter().map(|(l, _)| l).collect();
+}
+
+#[derive(Default)]
+struct Renderer {
+    width: usize,
+    depth: usize,
+    lines: Vec<(Line<'static>, bool)>,
+    current: Vec<Span<'static>>,
+}
+
+impl Renderer {
+    fn render_all(&mut self, nodes: &[Node], style: Style) {
+        for node in nodes {
+            self.render(node, style);
+        }
+    }
+
+    fn render(&mut self, node: &Node, style: Style) {
+        match node {
+            Node::Text(text) => self.push(text, style),
+            Node::Break => self.break_line(),
+            Node::Paragraph(children) => self.block(|r| r.render_all(children, style)),
+            Node::Emphasis(children) => {
+                self.render_all(children, style.add_modifier(Modifier::ITALIC))
+            }
+            Node::Strong(children) => self.render_all(children, style.add_modifier(Modifier::BOLD)),
+            Node::Strikethrough(children) => {
+                self.render_all(children, style.add_modifier(Modifier::CROSSED_OUT))
+            }
+            Node::Underline(children) => {
+                self.render_all(children, style.add_modifier(Modifier::UNDERLINED))
+            }
+            Node::Superscript(children) => {
+                self.push("^", style);
+                self.render_all(children, style);
+            }
+            Node::Link { href, children } => {
+                let link_style = style.fg(Color::Cyan).add_modifier(Modifier::UNDERLINED);
+                self.render_all(children, link_style);
+                if !href.is_empty() && plain_text(children).trim() != href {
+                    self.push(format!(" <{}>", href).as_str(), style.fg(Color::DarkGray));
+                }
+            }
+            Node::Quote {
+                author,
+                source,
+                children,
+            } => self.quote(author.as_deref(), source.as_deref(), children, style),
+            Node::List { ordered, items } => self.block(|r| {
+                for (i, item) in items.iter().enumerate() {
+                    let bullet = if *ordered {
+                        format!("{}. ", i + 1)
+                    } else {
+                        String::from("\u{2022} ")
+                    };
+                    r.push(bullet.as_str(), style);
+                    r.render_all(item, style);
+                    r.end_line();
+                }
+            }),
+            Node::Image { src, alt } => {
+                let label = if alt.is_empty() { src } else { alt };
+                self.push(
+                    format!("[image: {}]", label).as_str(),
+                    style.fg(Color::DarkGray),
+                );
+            }
+            Node::Embed { src } => {
+                self.push(
+                    format!("[embed: {}]", src).as_str(),
+                    style.fg(Color::DarkGray),
+                );
+            }
+        }
+    }
+
+    fn quote(
+        &mut self,
+        author: Option<&str>,
+        source: Option<&str>,
+        children: &[Node],
+        style: Style,
+    ) {
+        self.end_line();
+        self.break_line();
+        self.depth += 1;
+
+        let header = match author {
+            Some(author) => format!("{} wrote:", author),
+            None => String::from("Quote:"),
+        };
+        self.push(
+            header.as_str(),
+            style.fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        );
+        if let Some(source) = source {
+            self.push(format!(" <{}>", source).as_str(), style.fg(Color::DarkGray));
+        }
+        self.end_line();
+        // The header already separates the quote from what precedes it
+        if let Some(last) = self.lines.last_mut() {
+            last.1 = true;
+        }
+
+        self.render_all(
+            children,
+            style.fg(Color::Gray).add_modifier(Modifier::ITALIC),
+        );
+        self.end_line();
+        self.trim_blank();
+        if let Some(last) = self.lines.last_mut() {
+            last.1 = false;
+        }
+
+        self.depth -= 1;
+        self.break_line();
+    }
+
+    fn push(&mut self, text: &str, style: Style) {
+        let text = if self.current.is_empty() {
+            text.trim_start()
+        } else {
+            text
+        };
+        if !text.is_empty() {
+            self.current.push(Span::styled(text.to_string(), style));
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.current.is_empty() {
+            self.break_line();
+        }
+    }
+
+    /// Emits the line being built, wrapped to the available width. An empty
+    /// line is only emitted if the previous one wasn't empty too.
+    fn break_line(&mut self) {
+        let spans = std::mem::take(&mut self.current);
+        if spans.is_empty() {
+            if self.lines.last().is_none_or(|(_, blank)| *blank) {
+                return;
+            }
+            self.lines.push((self.prefixed(vec![]), true));
+            return;
+        }
+
+        let available = self
+            .width
+            .saturating_sub(QUOTE_PREFIX.width() * self.depth)
+            .max(10);
+        for row in wrap(spans, available) {
+            self.lines.push((self.prefixed(row), false));
+        }
+    }
+
+    fn prefixed(&self, mut spans: Vec<Span<'static>>) -> Line<'static> {
+        if self.depth > 0 {
+            let prefix = QUOTE_PREFIX.repeat(self.depth);
+            spans.insert(
+                0,
+                Span::styled(prefix, Style::default().fg(Color::DarkGray)),
+            );
+        }
+        return Line::from(spans);
+    }
+
+    fn trim_blank(&mut self) {
+        while self.lines.last().is_some_and(|(_, blank)| *blank) {
+            self.lines.pop();
+        }
+    }
+
+    fn block(&mut self, content: impl FnOnce(&mut Self)) {
+        self.end_line();
+        self.break_line();
+        content(self);
+        self.end_line();
+        self.break_line();
+    }
+}
+
+/// Splits spans on word boundaries so that no row exceeds `width`, words
+/// longer than a whole row are broken up.
+fn wrap(spans: Vec<Span<'static>>, width: usize) -> Vec<Vec<Span<'static>>> {
+    let mut rows = vec![vec![]];
+    let mut row_width = 0;
+
+    for span in spans {
+        for word in span.content.split_inclusive(' ') {
+            let mut word = word;
+            if row_width + word.trim_end().width() > width && row_width > 0 {
+                rows.push(vec![]);
+                row_width = 0;
+                word = word.trim_start();
+            }
+            while word.width() > width {
+                let split = word
+                    .char_indices()
+                    .scan(0, |w, (i, c)| {
+                        *w += c.width().unwrap_or(0);
+                        Some((i, *w))
+                    })
+                    .find(|(_, w)| *w > width - row_width)
+                    .map(|(i, _)| i)
+                    .unwrap_or(word.len());
+                if split == 0 {
+                    break;
+                }
+                rows.last_mut()
+                    .unwrap()
+                    .push(Span::styled(word[..split].to_string(), span.style));
+                rows.push(vec![]);
+                row_width = 0;
+                word = &word[split..];
+            }
+            if !word.is_empty() {
+                row_width += word.width();
+                rows.last_mut()
+                    .unwrap()
+                    .push(Span::styled(word.to_string(), span.style));
+            }
+        }
+    }
+    return rows;
+}
+
+pub fn plain_text(nodes: &[Node]) -> String {
+    return nodes.iter().fold(String::new(), |mut acc, node| {
+        match node {
+            Node::Text(t) => acc.push_str(t),
+            Node::Break => acc.push('\n'),
+            Node::Paragraph(c)
+            | Node::Emphasis(c)
+            | Node::Strong(c)
+            | Node::Strikethrough(c)
+            | Node::Underline(c)
+            | Node::Superscript(c)
+            | Node::Link { children: c, .. }
+            | Node::Quote { children: c, .. } => acc.push_str(plain_text(c).as_str()),
+            Node::List { items, .. } => {
+                for item in items {
+                    acc.push_str(plain_text(item).as_str());
+                    acc.push('\n');
+                }
+            }
+            Node::Image { alt, .. } => acc.push_str(alt),
+            Node::Embed { .. } => (),
+        }
+        acc
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::*;
+
+    fn parse(html: &str) -> Vec<Node> {
+        return Node::from_children(Html::parse_fragment(html).root_element());
+    }
+
+    fn text(lines: &[Line]) -> Vec<String> {
+        return lines
+            .iter()
+            .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect();
+    }
+
+    const NESTED: &str = r#"<blockquote><div>
+        Outer
+        <blockquote><div>Inner<span>— <a href="/discussion/comment/1">Bob</a></span></div></blockquote>
+        <span>— <a href="/discussion/comment/2">Ann</a></span>
+    </div></blockquote>"#;
+
+    #[test]
+    fn nested_quotes_keep_their_attribution() {
+        let nodes = parse(NESTED);
+        let [Node::Quote {
+            author,
+            source,
+            children,
+        }] = &nodes[..]
+        else {
+            panic!("expected a single quote, got {:?}", nodes);
+        };
+        assert_eq!(author.as_deref(), Some("Ann"));
+        assert_eq!(source.as_deref(), Some("/discussion/comment/2"));
+        let inner = children.iter().find_map(|n| match n {
+            Node::Quote { author, .. } => Some(author.as_deref()),
+            _ => None,
+        });
+        assert_eq!(inner, Some(Some("Bob")));
+    }
+
+    #[test]
+    fn nested_quotes_get_a_bar_per_level() {
+        let lines = text(&render(&parse(NESTED), 80));
+        assert!(lines[0].starts_with("│ Ann wrote:"));
+        assert!(lines.contains(&String::from("│ │ Bob wrote: </discussion/comment/1>")));
+        assert!(lines.contains(&String::from("│ │ Inner")));
+    }
+
+    #[test]
+    fn quotes_without_attribution_keep_their_content() {
+        let nodes = parse("<blockquote>Just <b>this</b><span>not a dash</span></blockquote>");
+        let [Node::Quote {
+            author: None,
+            source: None,
+            children,
+        }] = &nodes[..]
+        else {
+            panic!("expected an anonymous quote, got {:?}", nodes);
+        };
+        assert_eq!(plain_text(children), "Just thisnot a dash");
+        let lines = text(&render(&nodes, 80));
+        assert_eq!(lines, ["│ Quote:", "│ Just thisnot a dash"]);
+    }
+
+    #[test]
+    fn render_survives_narrow_widths() {
+        let html = format!(
+            "{}<p>{} 漢字漢字漢字 <a href=\"https://example.com/{}\">link</a></p>",
+            NESTED,
+            "x".repeat(50),
+            "y".repeat(30)
+        );
+        let nodes = parse(html.as_str());
+        for width in 0..12 {
+            let lines = render(&nodes, width);
+            assert!(!lines.is_empty());
+            // Quote bars aside, content is wrapped to at least 10 columns
+            for line in text(&lines) {
+                let content = line.trim_start_matches(QUOTE_PREFIX).trim_end();
+                assert!(content.width() <= width.max(10) as usize, "{:?}", line);
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_breaks_long_and_wide_words() {
+        let spans = vec![Span::raw("abcdef 漢字漢字 g")];
+        for width in 0..4 {
+            let rows = wrap(spans.clone(), width);
+            let joined: String = rows.iter().flatten().map(|s| s.content.as_ref()).collect();
+            assert_eq!(joined.replace(' ', ""), "abcdef漢字漢字g");
+        }
+        let rows = wrap(spans, 4);
+        assert!(rows.iter().all(|r| r
+            .iter()
+            .map(|s| s.content.trim_end().width())
+            .sum::<usize>()
+            <= 4));
+    }
+}
@@ -0,0 +1,78 @@
+use std::{fmt::Display, io};
+
+use reqwest::StatusCode;
+
+/// Failures of the `api` and `model` modules.
+#[derive(Debug)]
+pub enum Error {
+    /// The request couldn't be sent or its body couldn't be read
+    Network(reqwest::Error),
+    /// The forum answered with an unsuccessful status code
+    Status {
+        url: String,
+        status: StatusCode,
+    },
+    /// Offline mode was asked for a page that was never fetched
+    NotCached(String),
+    Io(io::Error),
+    /// Stored data couldn't be encoded or decoded
+    Storage(String),
+    /// A file was written by a newer client in a format this one can't read
+    UnsupportedVersion {
+        file: &'static str,
+        version: u16,
+    },
+}
+
+impl Error {
+    /// Whether trying again shortly after has any chance of succeeding.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            Error::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Error::NotCached(_)
+            | Error::Io(_)
+            | Error::Storage(_)
+            | Error::UnsupportedVersion { .. } => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Network(e) => write!(f, "Network error: {}", e),
+            Error::Status { url, status } => write!(f, "{} answered {}", url, status),
+            Error::NotCached(path) => write!(f, "{} is not available offline", path),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Storage(e) => write!(f, "Storage error: {}", e),
+            Error::UnsupportedVersion { file, version } => write!(
+                f,
+                "{} format version {} is newer than this client supports",
+                file, version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(value: reqwest::Error) -> Self {
+        Error::Network(value)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(value: io::Error) -> Self {
+        Error::Io(value)
+    }
+}
+
+impl From<serde_cbor::Error> for Error {
+    fn from(value: serde_cbor::Error) -> Self {
+        Error::Storage(value.to_string())
+    }
+}
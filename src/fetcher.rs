@@ -0,0 +1,122 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{
+    api::{get_categories, get_thread, get_threads, ForumClient},
+    error::Error,
+    model::TabState,
+    overview::{Category, Source, ThreadOverview},
+    thread::ThreadData,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    Threads {
+        source: Source,
+        page: u16,
+    },
+    Thread {
+        thread: ThreadOverview,
+        page: u16,
+        /// Ask the forum even if the cached page is recent
+        revalidate: bool,
+    },
+    Categories,
+}
+
+impl Request {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Request::Threads { .. } => "Fetching threads overviews...",
+            Request::Thread { .. } => "Fetching thread data...",
+            Request::Categories => "Fetching categories...",
+        }
+    }
+}
+
+pub enum Payload {
+    /// Threads along with the time they were cached at
+    Threads(Vec<ThreadOverview>, Option<u64>),
+    Thread(ThreadData),
+    Categories(Vec<Category>),
+}
+
+pub struct Response {
+    pub request: Request,
+    pub result: Result<Payload, Error>,
+}
+
+/// Performs requests on a worker thread, results are collected with
+/// [`Fetcher::try_recv`] so that the UI never blocks on the network.
+pub struct Fetcher {
+    requests: Sender<(u64, TabState, Request)>,
+    responses: Receiver<(u64, TabState, Response)>,
+    generation: Arc<AtomicU64>,
+}
+
+impl Fetcher {
+    pub fn new(client: ForumClient) -> Self {
+        let (requests, worker_requests) = mpsc::channel::<(u64, TabState, Request)>();
+        let (worker_responses, responses) = mpsc::channel();
+        let generation = Arc::new(AtomicU64::new(0));
+        let current = generation.clone();
+
+        thread::spawn(move || {
+            for (gen, tab, request) in worker_requests {
+                // Skip whatever was queued before a cancellation
+                if gen != current.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let result = match &request {
+                    Request::Threads { source, page } => {
+                        get_threads(&client, source, *page).map(|(t, c)| Payload::Threads(t, c))
+                    }
+                    Request::Thread {
+                        thread,
+                        page,
+                        revalidate,
+                    } => get_thread(&client, thread, *page, *revalidate).map(Payload::Thread),
+                    Request::Categories => get_categories(&client).map(Payload::Categories),
+                };
+                if worker_responses
+                    .send((gen, tab, Response { request, result }))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        return Fetcher {
+            requests,
+            responses,
+            generation,
+        };
+    }
+
+    pub fn send(&self, tab: TabState, request: Request) {
+        let gen = self.generation.load(Ordering::SeqCst);
+        // The worker only stops once the fetcher is dropped
+        let _ = self.requests.send((gen, tab, request));
+    }
+
+    /// Drops queued requests and discards the responses of those in flight.
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn try_recv(&self) -> Option<(TabState, Response)> {
+        while let Ok((gen, tab, response)) = self.responses.try_recv() {
+            if gen == self.generation.load(Ordering::SeqCst) {
+                return Some((tab, response));
+            }
+        }
+        return None;
+    }
+}
@@ -0,0 +1,36 @@
+use scraper::{ElementRef, Html, Selector};
+
+/// Every element in the document having an attribute whose value is exactly
+/// `value` (e.g. `class="Comment"`).
+pub fn elements_with_attr_value<'a>(
+    document: &'a Html,
+    value: &'a str,
+) -> impl Iterator<Item = ElementRef<'a>> + 'a {
+    document
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .filter(move |e| e.value().attrs().any(|(_, v)| v == value))
+}
+
+/// First descendant of `element` matching the CSS `selectors`.
+pub fn select_first<'a>(element: ElementRef<'a>, selectors: &str) -> Option<ElementRef<'a>> {
+    let selector = Selector::parse(selectors).ok()?;
+    return element.select(&selector).next();
+}
+
+pub fn attr(element: Option<ElementRef>, name: &str) -> String {
+    return element
+        .and_then(|e| e.value().attr(name))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+}
+
+pub fn text(element: Option<ElementRef>) -> String {
+    return element
+        .map(|e| e.text().collect::<String>())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+}
@@ -0,0 +1,373 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{document::plain_text, overview::ThreadOverview, thread::ThreadComment};
+
+const MAX_HITS: usize = 200;
+/// Comments kept at most, the threads indexed first are dropped beyond it
+const MAX_COMMENTS: usize = 100_000;
+const SNIPPET_WIDTH: usize = 80;
+
+/// Full-text index of every comment that was fetched. Only the comments are
+/// stored, the word lookup table is rebuilt when loading.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    threads: Vec<IndexedThread>,
+    #[serde(skip)]
+    by_url: HashMap<String, usize>,
+    /// Comments containing each word, as thread and comment positions
+    #[serde(skip)]
+    postings: HashMap<String, Vec<(u32, u32)>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IndexedThread {
+    pub thread: ThreadOverview,
+    pub comments: Vec<IndexedComment>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IndexedComment {
+    pub author: String,
+    /// Lowercase words separated by a single space
+    pub text: String,
+}
+
+/// A comment matching a query.
+pub struct Hit {
+    pub thread: ThreadOverview,
+    pub comment: usize,
+    pub author: String,
+    pub snippet: String,
+}
+
+/// Words must all appear in a comment, phrases (between double quotes) must
+/// appear as is and `author:name` restricts the author.
+#[derive(Default)]
+pub struct Query {
+    words: Vec<String>,
+    phrases: Vec<String>,
+    author: Option<String>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Query::default();
+        for (i, part) in input.split('"').enumerate() {
+            // Odd parts are between quotes
+            if i % 2 == 1 {
+                let phrase = words(part).join(" ");
+                if !phrase.is_empty() {
+                    query.phrases.push(phrase);
+                }
+                continue;
+            }
+            for token in part.split_whitespace() {
+                match token.strip_prefix("author:") {
+                    Some(author) => query.author = Some(author.to_lowercase()),
+                    None => query.words.extend(words(token)),
+                }
+            }
+        }
+        return query;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.words.is_empty() && self.phrases.is_empty() && self.author.is_none();
+    }
+
+    fn terms(&self) -> Vec<String> {
+        let phrase_words = self.phrases.iter().flat_map(|p| p.split(' '));
+        return self
+            .words
+            .iter()
+            .map(|w| w.as_str())
+            .chain(phrase_words)
+            .map(String::from)
+            .collect();
+    }
+
+    fn matches(&self, comment: &IndexedComment) -> bool {
+        let text = format!(" {} ", comment.text);
+        let phrases = self
+            .phrases
+            .iter()
+            .all(|p| text.contains(format!(" {} ", p).as_str()));
+        let author = self
+            .author
+            .as_ref()
+            .is_none_or(|a| comment.author.to_lowercase().contains(a.as_str()));
+        return phrases && author;
+    }
+}
+
+/// Lowercase alphanumeric words of `text`.
+fn words(text: &str) -> Vec<String> {
+    return text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+}
+
+impl SearchIndex {
+    pub fn new(threads: Vec<IndexedThread>) -> Self {
+        let mut index = SearchIndex {
+            threads,
+            ..Default::default()
+        };
+        index.rebuild();
+        return index;
+    }
+
+    /// Fills the lookup tables once the comments have been deserialized.
+    pub fn rebuild(&mut self) {
+        self.by_url.clear();
+        self.postings.clear();
+        for t in 0..self.threads.len() {
+            self.by_url.insert(self.threads[t].thread.url.clone(), t);
+            for c in 0..self.threads[t].comments.len() {
+                self.post(t, c);
+            }
+        }
+    }
+
+    fn post(&mut self, thread: usize, comment: usize) {
+        let key = (thread as u32, comment as u32);
+        for word in self.threads[thread].comments[comment].text.split(' ') {
+            let posting = self.postings.entry(word.to_string()).or_default();
+            if posting.last() != Some(&key) {
+                posting.push(key);
+            }
+        }
+    }
+
+    /// Indexes the comments of `thread` found from position `start` on.
+    /// Comments never move within a thread, those already indexed are
+    /// skipped.
+    pub fn add_comments(
+        &mut self,
+        thread: &ThreadOverview,
+        start: usize,
+        comments: &[ThreadComment],
+    ) {
+        let t = match self.by_url.get(thread.url.as_str()) {
+            Some(&t) => t,
+            None => {
+                self.threads.push(IndexedThread {
+                    thread: thread.clone(),
+                    comments: vec![],
+                });
+                self.by_url
+                    .insert(thread.url.clone(), self.threads.len() - 1);
+                self.threads.len() - 1
+            }
+        };
+        self.threads[t].thread = thread.clone();
+
+        for (i, comment) in comments.iter().enumerate() {
+            if start + i != self.threads[t].comments.len() {
+                continue;
+            }
+            self.threads[t].comments.push(IndexedComment {
+                author: comment.author.clone(),
+                text: words(plain_text(&comment.text).as_str()).join(" "),
+            });
+            self.post(t, start + i);
+        }
+        self.trim();
+    }
+
+    /// Drops the threads indexed first once there are too many comments. A
+    /// tenth more is dropped so that this doesn't happen on every page.
+    fn trim(&mut self) {
+        let mut total: usize = self.threads.iter().map(|t| t.comments.len()).sum();
+        if total <= MAX_COMMENTS {
+            return;
+        }
+        let mut dropped = 0;
+        while total > MAX_COMMENTS - MAX_COMMENTS / 10 && dropped + 1 < self.threads.len() {
+            total -= self.threads[dropped].comments.len();
+            dropped += 1;
+        }
+        self.threads.drain(..dropped);
+        self.rebuild();
+    }
+
+    pub fn search(&self, query: &Query) -> Vec<Hit> {
+        let terms = query.terms();
+        let candidates: Vec<(u32, u32)> = if terms.is_empty() {
+            // Author only
+            self.threads
+                .iter()
+                .enumerate()
+                .flat_map(|(t, thread)| {
+                    (0..thread.comments.len()).map(move |c| (t as u32, c as u32))
+                })
+                .collect()
+        } else {
+            let mut sets = terms.iter().map(|w| {
+                self.postings
+                    .get(w)
+                    .map(|p| p.iter().copied().collect::<HashSet<_>>())
+                    .unwrap_or_default()
+            });
+            let first = sets.next().unwrap_or_default();
+            let common = sets.fold(first, |acc, s| acc.intersection(&s).copied().collect());
+            let mut common: Vec<(u32, u32)> = common.into_iter().collect();
+            common.sort();
+            common
+        };
+
+        return candidates
+            .into_iter()
+            .filter_map(|(t, c)| {
+                let thread = &self.threads[t as usize];
+                let comment = &thread.comments[c as usize];
+                if !query.matches(comment) {
+                    return None;
+                }
+                return Some(Hit {
+                    thread: thread.thread.clone(),
+                    comment: c as usize,
+                    author: comment.author.clone(),
+                    snippet: snippet(comment.text.as_str(), terms.first()),
+                });
+            })
+            .take(MAX_HITS)
+            .collect();
+    }
+}
+
+/// Part of the text around the first occurrence of `term`.
+fn snippet(text: &str, term: Option<&String>) -> String {
+    let at = term.and_then(|t| text.find(t.as_str())).unwrap_or_default();
+    let start = text[..at]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_WIDTH / 4)
+        .map_or(0, |(i, _)| i);
+    let snippet: String = text[start..].chars().take(SNIPPET_WIDTH).collect();
+    return if start > 0 {
+        format!("…{}", snippet)
+    } else {
+        snippet
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Node;
+
+    fn thread(url: &str) -> ThreadOverview {
+        return ThreadOverview {
+            title: String::from("Title"),
+            url: String::from(url),
+            author: String::from("Author"),
+            replies: None,
+            category: None,
+            last_activity: None,
+        };
+    }
+
+    fn comment(author: &str, text: &str) -> ThreadComment {
+        return ThreadComment {
+            author: String::from(author),
+            text: vec![Node::Text(String::from(text))],
+            date: None,
+        };
+    }
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::default();
+        let comments = [
+            comment("Ann", "Free will is an illusion."),
+            comment("Bob", "Is the will free, or is freedom an illusion?"),
+        ];
+        index.add_comments(&thread("discussion/1"), 0, &comments);
+        index.add_comments(
+            &thread("discussion/2"),
+            0,
+            &[comment("Ann", "Nothing here")],
+        );
+        return index;
+    }
+
+    fn found(index: &SearchIndex, query: &str) -> Vec<(String, usize)> {
+        return index
+            .search(&Query::parse(query))
+            .into_iter()
+            .map(|h| (h.thread.url, h.comment))
+            .collect();
+    }
+
+    #[test]
+    fn parse_splits_words_phrases_and_author() {
+        let query = Query::parse(r#"Free "Will IS" author:Ann"#);
+        assert_eq!(query.words, ["free"]);
+        assert_eq!(query.phrases, ["will is"]);
+        assert_eq!(query.author.as_deref(), Some("ann"));
+    }
+
+    #[test]
+    fn parse_keeps_an_unterminated_phrase() {
+        let query = Query::parse(r#"a "b c"#);
+        assert_eq!(query.words, ["a"]);
+        assert_eq!(query.phrases, ["b c"]);
+    }
+
+    #[test]
+    fn parse_of_blank_input_is_empty() {
+        assert!(Query::parse(r#"  "" "#).is_empty());
+    }
+
+    #[test]
+    fn search_requires_every_word() {
+        let index = index();
+        let both = vec![
+            (String::from("discussion/1"), 0),
+            (String::from("discussion/1"), 1),
+        ];
+        assert_eq!(found(&index, "illusion WILL"), both);
+        assert!(found(&index, "illusion nothing").is_empty());
+    }
+
+    #[test]
+    fn search_matches_phrases_in_order() {
+        let index = index();
+        assert_eq!(
+            found(&index, r#""free will""#),
+            [(String::from("discussion/1"), 0)]
+        );
+    }
+
+    #[test]
+    fn search_filters_by_author() {
+        let index = index();
+        assert_eq!(
+            found(&index, "author:bob"),
+            [(String::from("discussion/1"), 1)]
+        );
+        assert_eq!(
+            found(&index, "nothing author:ann"),
+            [(String::from("discussion/2"), 0)]
+        );
+    }
+
+    #[test]
+    fn add_comments_skips_indexed_positions() {
+        let mut index = index();
+        let comments = [
+            comment("Bob", "Is the will free"),
+            comment("Cy", "An illusion"),
+        ];
+        index.add_comments(&thread("discussion/1"), 1, &comments);
+        assert_eq!(found(&index, "illusion").len(), 3);
+        assert_eq!(
+            found(&index, "author:cy"),
+            [(String::from("discussion/1"), 2)]
+        );
+    }
+}
@@ -1,138 +1,184 @@
 #![allow(clippy::needless_return, arithmetic_overflow)]
 use std::{
     error::Error,
-    fs::{self, File},
-    io::{stdout, Write},
+    io::{stdout, Stdout, Write},
+    path::Path,
+    time::Duration,
 };
 
-use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
-};
+use config::Config;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use model::{update, Action, Model};
 use ratatui::{backend::CrosstermBackend, Terminal};
-use ui::view;
-use uzers::get_current_username;
-use xz::{read::XzDecoder, write::XzEncoder};
+use storage::{load_bookmarks, load_index, load_reads, save_bookmarks, save_index, save_reads};
+use ui::{install_panic_hook, view, TerminalGuard};
 
-use crate::model::TabState;
+use crate::{
+    api::ForumClient,
+    fetcher::Fetcher,
+    model::{PromptKind, TabState},
+};
 
 mod api;
+mod bookmarks;
+mod cache;
+mod config;
+mod dates;
+mod document;
+mod error;
+mod fetcher;
+mod html;
+mod index;
 mod model;
 mod overview;
+mod paths;
+mod reads;
+mod search;
+mod storage;
 mod thread;
 mod ui;
 
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
 fn main() -> Result<(), Box<dyn Error>> {
-    stdout().execute(EnterAlternateScreen)?;
-    enable_raw_mode()?;
-    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-    terminal.clear()?;
+    let config = Config::load()?;
+    config.paths.prepare()?;
+
+    let save_path = config.paths.bookmarks();
+    let bookmarks = load_bookmarks(&save_path)?.unwrap_or_default();
+    let reads_path = config.paths.reads();
+    let reads = load_reads(&reads_path)?;
+    let index_path = config.paths.index();
+    let index = load_index(&index_path);
+
+    let fetcher = Fetcher::new(ForumClient::new(&config)?);
+    install_panic_hook();
+    let (guard, mut terminal) = TerminalGuard::enter()?;
+    let mut model = Model::new(&fetcher, config.prefetch, bookmarks, reads, index);
+    let result = run(&mut terminal, &mut model, &fetcher, &save_path, &reads_path);
+    drop(guard);
+
+    print!("Saving bookmarks... ");
+    stdout().flush()?;
+    save_bookmarks(&save_path, &model.bookmarks)?;
+    save_reads(&reads_path, &model.reads)?;
+    save_index(&index_path, &model.index)?;
 
-    let user = get_current_username().unwrap().into_string().unwrap();
-    let save_path = format!("/home/{}/.cache/oxi-phil/bookmarks.txt", &user);
-    let reader = fs::File::open(&save_path);
-    let data = if reader.is_ok() {
-        let buf = XzDecoder::new(reader?);
-        serde_cbor::from_reader(buf)?
-    } else {
-        Model::new_bookmarks()
-    };
+    println!("done");
+    return result;
+}
 
-    // TODO Instead of having 2 models make a bookmark struct within model
-    let mut model = [Model::new(&mut terminal), data];
-    let mut tab = TabState::Home;
+fn run(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    model: &mut Model,
+    fetcher: &Fetcher,
+    save_path: &Path,
+    reads_path: &Path,
+) -> Result<(), Box<dyn Error>> {
     let mut running = true;
 
     while running {
-        let current_model = match tab {
-            TabState::Home => &model[0],
-            TabState::Bookmarks => &model[1],
-        };
-        terminal.draw(|frame| view(current_model, frame))?;
-        if let Event::Key(key) = event::read().unwrap() {
+        while let Some((tab, response)) = fetcher.try_recv() {
+            model.apply(response, fetcher, &tab);
+        }
+        if model.bookmarks.unsaved {
+            // Failures are reported once, saving is tried again on the next
+            // change and on exit
+            model.bookmarks.unsaved = false;
+            if let Err(e) = save_bookmarks(save_path, &model.bookmarks) {
+                model.fail(e, None);
+            }
+        }
+        if model.reads.unsaved {
+            model.reads.unsaved = false;
+            if let Err(e) = save_reads(reads_path, &model.reads) {
+                model.fail(e, None);
+            }
+        }
+
+        terminal.draw(|frame| view(model, frame))?;
+        if !event::poll(REDRAW_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
             if key.kind == KeyEventKind::Press {
                 let m = match key.code {
+                    _ if model.error.is_some() => match key.code {
+                        KeyCode::Esc | KeyCode::Enter => Action::DismissError,
+                        KeyCode::Char('r') => Action::Retry,
+                        KeyCode::Char('q') => Action::Quit,
+                        _ => Action::Nothing,
+                    },
+                    _ if model.category_picker.is_some() => match key.code {
+                        KeyCode::Esc => Action::CloseCategories,
+                        KeyCode::Enter => Action::PickCategory,
+                        KeyCode::Up => Action::PrevCategory,
+                        KeyCode::Down => Action::NextCategory,
+                        KeyCode::Char('q') => Action::Quit,
+                        _ => Action::Nothing,
+                    },
+                    _ if model.index_results.is_some() => match key.code {
+                        KeyCode::Esc => Action::CloseHits,
+                        KeyCode::Enter => Action::OpenHit,
+                        KeyCode::Up => Action::PrevHit,
+                        KeyCode::Down => Action::NextHit,
+                        KeyCode::Char('q') => Action::Quit,
+                        _ => Action::Nothing,
+                    },
+                    _ if model.prompt.is_some() => match key.code {
+                        KeyCode::Esc => Action::PromptCancel,
+                        KeyCode::Enter => Action::PromptSubmit,
+                        KeyCode::Backspace => Action::PromptBackspace,
+                        KeyCode::Char(c) => Action::PromptInput(c),
+                        _ => Action::Nothing,
+                    },
                     KeyCode::Up => Action::PrevThread,
                     KeyCode::Down => Action::NextThread,
                     KeyCode::PageUp => Action::ScrollUp,
                     KeyCode::PageDown => Action::ScrollDown,
+                    KeyCode::Char('n') if model.comment_search.is_some() => Action::NextMatch,
+                    KeyCode::Char('N') if model.comment_search.is_some() => Action::PrevMatch,
+                    // Pending fetches are cancelled first
+                    KeyCode::Esc if model.comment_search.is_some() && model.pending.is_empty() => {
+                        Action::ClearCommentSearch
+                    }
                     KeyCode::Char('n') | KeyCode::Right => Action::NextComment,
                     KeyCode::Char('p') | KeyCode::Left => Action::PrevComment,
                     KeyCode::Char('q') => Action::Quit,
                     KeyCode::Char('c') => Action::CleanComments,
+                    KeyCode::Char('g') => Action::FirstUnread,
                     KeyCode::Char(n) if n.is_ascii_digit() => {
                         Action::Moltiply(n.to_digit(10).unwrap())
                     }
                     KeyCode::Esc => Action::Nullify,
-                    KeyCode::Char('z') => {
-                        tab = TabState::Home;
-                        Action::Nothing
-                    }
-                    KeyCode::Char('x') => {
-                        tab = TabState::Bookmarks;
-                        Action::Nothing
-                    }
-                    /* TODO Move logic into model when bookmarks
-                     * will have a struct within model itself
-                     */
-                    KeyCode::Char('b') => {
-                        if tab == TabState::Home {
-                            let mm = model.first().unwrap();
-                            let over = mm
-                                .overview
-                                .get(mm.selected_thread as usize)
-                                .unwrap()
-                                .clone();
-                            let data = mm
-                                .data
-                                .data
-                                .get(mm.selected_thread as usize)
-                                .unwrap()
-                                .clone();
-                            model.get_mut(1).unwrap().add_bookmark(&over, &data);
-                        }
-                        Action::Nothing
-                    }
-                    KeyCode::Char('u') => {
-                        let over = current_model
-                            .overview
-                            .get(current_model.selected_thread as usize)
-                            .unwrap()
-                            .clone();
-                        model.get_mut(1).unwrap().delete_bookmark(&over);
-                        Action::Nothing
-                    }
+                    KeyCode::Char('z') => Action::SwitchTab(TabState::Home),
+                    KeyCode::Char('x') => Action::SwitchTab(TabState::Bookmarks),
+                    KeyCode::Char('v') => Action::SwitchTab(TabState::Search),
+                    KeyCode::Char('s') => Action::OpenPrompt(PromptKind::Search),
+                    KeyCode::Char('/') => Action::OpenPrompt(PromptKind::CommentSearch),
+                    KeyCode::Char('i') => Action::OpenPrompt(PromptKind::IndexSearch),
+                    KeyCode::Char('A') => Action::LoadAll,
+                    KeyCode::Char('b') => Action::AddBookmark,
+                    KeyCode::Char('u') => Action::RemoveBookmark,
+                    KeyCode::Char('K') => Action::MoveBookmarkUp,
+                    KeyCode::Char('J') => Action::MoveBookmarkDown,
+                    KeyCode::Char('R') => Action::RefreshBookmarks,
+                    KeyCode::Char('f') => Action::OpenPrompt(PromptKind::Folder),
+                    KeyCode::Char('t') => Action::OpenPrompt(PromptKind::Tags),
+                    KeyCode::Char('F') => Action::OpenPrompt(PromptKind::BookmarkFilter),
+                    KeyCode::Char('C') => Action::OpenCategories,
+                    KeyCode::Char('T') => Action::OpenPrompt(PromptKind::ThreadFilter),
+                    KeyCode::Char('o') => Action::CycleSort,
                     _ => Action::Nothing,
                 };
 
                 if m == Action::Quit {
                     running = false;
-                } else {
-                    let current_model = match tab {
-                        TabState::Home => &mut model[0],
-                        TabState::Bookmarks => &mut model[1],
-                    };
-                    update(current_model, m, &mut terminal, &tab).unwrap();
-                    if m != Action::Nothing {
-                        terminal.draw(|frame| view(current_model, frame))?;
-                    }
+                } else if let Err(e) = update(model, m, fetcher) {
+                    model.fail(e, None);
                 }
             }
         }
     }
-    stdout().execute(LeaveAlternateScreen)?;
-    disable_raw_mode()?;
-
-    print!("Saving bookmarks... ");
-    stdout().flush()?;
-    let file = File::create(&save_path)?;
-    let data = serde_cbor::to_vec(&model[1])?;
-    let mut compressor = XzEncoder::new(file, 9);
-    compressor.write_all(&data)?;
-    compressor.finish()?;
-
-    println!("done");
     return Ok(());
 }
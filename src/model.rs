@@ -1,37 +1,114 @@
-use std::{error::Error, io::Stdout};
+use std::cmp::Reverse;
 
-use ratatui::{backend::CrosstermBackend, Terminal};
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
 
 use crate::{
-    api::{get_thread, get_threads},
-    overview::ThreadOverview,
+    bookmarks::{Bookmarks, Filter},
+    config::Prefetch,
+    error::Error,
+    fetcher::{Fetcher, Payload, Request, Response},
+    index::{Hit, Query, SearchIndex},
+    overview::{Category, SortMode, Source, ThreadFilter, ThreadOverview},
+    reads::ReadMarkers,
+    search::comment_matches,
     thread::ThreadData,
 };
 
-#[derive(Default, Deserialize, Serialize)]
 pub struct Model {
+    pub tab: TabState,
+    pub home: ThreadList,
+    pub bookmarks: Bookmarks,
+    pub search: ThreadList,
+    pub reads: ReadMarkers,
+    pub index: SearchIndex,
+    /// Comments of the read threads matching a query, shown over the tabs
+    pub index_results: Option<IndexResults>,
+    /// Subforums, fetched the first time they're asked for
+    pub categories: Vec<Category>,
+    /// Selected line of the category list while it's open, the first one
+    /// stands for the whole forum
+    pub category_picker: Option<usize>,
+    pub multiplier: Vec<u32>,
+    /// Requests sent to the fetcher along with the tab they were sent for
+    pub pending: Vec<(TabState, Request)>,
+    pub error: Option<ErrorPopup>,
+    /// Text being typed by the user, keys go there while it's open
+    pub prompt: Option<Prompt>,
+    /// Looked for in the comments of the selected thread
+    pub comment_search: Option<String>,
+    /// A forward search ran out of loaded comments, it goes on with the
+    /// next page of the selected thread
+    pub seeking_match: bool,
+    /// Threads whose pages are fetched up to the last one
+    pub loading_all: Vec<(TabState, String)>,
+    pub prefetch: Prefetch,
+}
+
+/// Threads shown in a tab and the position of the user among them.
+#[derive(Default)]
+pub struct ThreadList {
     pub overview: Vec<ThreadOverview>,
     pub selected_thread: u16,
     pub overview_page: u16,
-    pub data: ThreadsModel,
-    pub viewer_scroll: u16,
-    pub multiplier: Vec<u32>,
-    #[serde(skip_serializing, skip_deserializing)]
-    pub http_client: Client,
+    pub source: Source,
+    /// Set once a page brought no new thread
+    pub complete: bool,
+    /// Threads data, kept parallel to `overview`. A thread whose
+    /// `comment_page` is 0 hasn't been fetched yet.
+    pub threads: Vec<ThreadData>,
+    /// When the oldest overview page was fetched, if it came from the cache
+    pub overview_cached_at: Option<u64>,
+    pub filter: Option<ThreadFilter>,
+    pub sort: SortMode,
+}
+
+pub struct ErrorPopup {
+    pub message: String,
+    /// Request that failed, sent again on retry
+    pub retry: Option<(TabState, Request)>,
+}
+
+pub struct IndexResults {
+    pub query: String,
+    pub hits: Vec<Hit>,
+    pub selected: usize,
+}
+
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: String,
 }
 
-#[derive(Default, Serialize, Deserialize)]
-pub struct ThreadsModel {
-    pub data: Vec<ThreadData>,
-    pub selected_comment: u16,
+#[derive(PartialEq, Clone, Copy)]
+pub enum PromptKind {
+    Folder,
+    Tags,
+    BookmarkFilter,
+    Search,
+    CommentSearch,
+    IndexSearch,
+    ThreadFilter,
 }
 
-#[derive(PartialEq)]
+impl PromptKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PromptKind::Folder => "Folder",
+            PromptKind::Tags => "Tags",
+            PromptKind::BookmarkFilter => "Filter (folder or #tag)",
+            PromptKind::Search => "Search the forum",
+            PromptKind::CommentSearch => "Search the thread",
+            PromptKind::IndexSearch => "Search read threads",
+            PromptKind::ThreadFilter => "Filter threads (title, author:name, replies:n)",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
 pub enum TabState {
     Home,
     Bookmarks,
+    Search,
 }
 
 #[derive(PartialEq, Clone, Copy)]
@@ -47,166 +124,1054 @@ pub enum Action {
     Moltiply(u32),
     Nullify,
     CleanComments,
+    DismissError,
+    Retry,
+    SwitchTab(TabState),
+    AddBookmark,
+    RemoveBookmark,
+    FirstUnread,
+    NextMatch,
+    PrevMatch,
+    ClearCommentSearch,
+    LoadAll,
+    MoveBookmarkUp,
+    MoveBookmarkDown,
+    RefreshBookmarks,
+    OpenCategories,
+    CloseCategories,
+    NextCategory,
+    PrevCategory,
+    PickCategory,
+    OpenPrompt(PromptKind),
+    PromptInput(char),
+    PromptBackspace,
+    PromptSubmit,
+    PromptCancel,
+    NextHit,
+    PrevHit,
+    OpenHit,
+    CloseHits,
+    CycleSort,
+}
+
+impl ThreadList {
+    /// The selected thread, unless the filter hides it.
+    pub fn selected(&self) -> Option<&ThreadOverview> {
+        let n = self.selected_thread as usize;
+        return self.overview.get(n).filter(|_| self.is_shown(n));
+    }
+
+    pub fn selected_data(&self) -> Option<&ThreadData> {
+        let n = self.selected_thread as usize;
+        return self.threads.get(n).filter(|_| self.is_shown(n));
+    }
+
+    pub fn push(&mut self, over: ThreadOverview, data: ThreadData) {
+        self.overview.push(over);
+        self.threads.push(data);
+    }
+
+    /// Removes the `n`th thread, the selection stays on the same thread
+    /// unless it's the removed one.
+    pub fn remove(&mut self, n: usize) {
+        if n >= self.overview.len() {
+            return;
+        }
+        self.overview.remove(n);
+        self.threads.remove(n);
+        let selected = self.selected_thread as usize;
+        if n < selected || (n == selected && selected == self.overview.len() && selected > 0) {
+            self.selected_thread -= 1;
+        }
+    }
+
+    /// Exchanges the places of two threads, the selection follows them.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.overview.swap(a, b);
+        self.threads.swap(a, b);
+        let selected = self.selected_thread as usize;
+        if selected == a {
+            self.selected_thread = b as u16;
+        } else if selected == b {
+            self.selected_thread = a as u16;
+        }
+    }
+
+    /// Whether the `n`th thread exists and matches the filter.
+    fn is_shown(&self, n: usize) -> bool {
+        return self
+            .overview
+            .get(n)
+            .is_some_and(|o| self.filter.as_ref().is_none_or(|f| f.matches(o)));
+    }
+
+    /// Positions of the threads matching the filter, in the order they're
+    /// shown.
+    pub fn shown(&self) -> Vec<usize> {
+        let mut shown: Vec<usize> = (0..self.overview.len())
+            .filter(|&n| self.is_shown(n))
+            .collect();
+        let over = &self.overview;
+        let lower = |s: &str| s.to_lowercase();
+        match self.sort {
+            SortMode::Listed => (),
+            // Threads without a known date or count go last
+            SortMode::Activity => shown.sort_by_key(|&n| Reverse(self.last_activity(n))),
+            SortMode::Replies => shown.sort_by_key(|&n| Reverse(over[n].replies)),
+            SortMode::Author => shown.sort_by_key(|&n| lower(&over[n].author)),
+            SortMode::Title => shown.sort_by_key(|&n| lower(&over[n].title)),
+        }
+        return shown;
+    }
+
+    /// Latest date between the one listed and those of the loaded comments
+    /// of the `n`th thread.
+    pub fn last_activity(&self, n: usize) -> Option<DateTime<Utc>> {
+        let listed = self.overview.get(n).and_then(|o| o.last_activity);
+        let loaded = self
+            .threads
+            .get(n)
+            .and_then(|t| t.comments.last())
+            .and_then(|c| c.date);
+        return listed.max(loaded);
+    }
+
+    fn next_page(&self) -> Request {
+        return Request::Threads {
+            source: self.source.clone(),
+            page: self.overview_page + 1,
+        };
+    }
+
+    fn selected_data_mut(&mut self) -> Option<&mut ThreadData> {
+        let n = self.selected_thread as usize;
+        if !self.is_shown(n) {
+            return None;
+        }
+        return self.threads.get_mut(n);
+    }
 }
 
 impl Model {
-    pub fn add_bookmark(&mut self, over: &ThreadOverview, data: &ThreadData) {
-        if self.overview.iter().any(|x| x.url == over.url) {
+    pub fn list(&self) -> &ThreadList {
+        return self.list_for(&self.tab);
+    }
+
+    fn list_for(&self, tab: &TabState) -> &ThreadList {
+        return match tab {
+            TabState::Home => &self.home,
+            TabState::Search => &self.search,
+            TabState::Bookmarks => &self.bookmarks.list,
+        };
+    }
+
+    fn list_mut(&mut self, tab: &TabState) -> &mut ThreadList {
+        return match tab {
+            TabState::Home => &mut self.home,
+            TabState::Search => &mut self.search,
+            TabState::Bookmarks => &mut self.bookmarks.list,
+        };
+    }
+
+    fn request(&mut self, fetcher: &Fetcher, tab: &TabState, request: Request) {
+        if self.pending.iter().any(|(t, r)| t == tab && *r == request) {
             return;
         }
-        self.overview.push(over.clone());
-        self.data.data.push(data.clone());
+        fetcher.send(*tab, request.clone());
+        self.pending.push((*tab, request));
     }
 
-    pub fn delete_bookmark(&mut self, over: &ThreadOverview) {
-        if let Some(n) = self.overview.iter().position(|x| x == over) {
-            self.overview.remove(n);
-            self.data.data.remove(n);
-            if self.selected_thread > 0 {
-                self.selected_thread -= 1;
+    /// Whether a page of `thread` is being fetched for the current tab.
+    pub fn is_loading(&self, thread: &ThreadOverview) -> bool {
+        return self.pending.iter().any(|(tab, r)| {
+            *tab == self.tab
+                && matches!(r, Request::Thread { thread: t, .. } if t.url == thread.url)
+        });
+    }
+
+    /// Requests the first page of the `n`th thread unless it's already
+    /// available or the filter hides it.
+    fn load_thread(&mut self, n: usize, fetcher: &Fetcher, tab: &TabState) {
+        let list = self.list_for(tab);
+        let loaded = list.threads.get(n).is_none_or(|t| t.comment_page > 0);
+        if loaded || !list.is_shown(n) {
+            return;
+        }
+        if let Some(over) = list.overview.get(n) {
+            let request = Request::Thread {
+                thread: over.clone(),
+                page: 1,
+                revalidate: false,
+            };
+            self.request(fetcher, tab, request);
+        }
+    }
+
+    /// Loads the selected thread and whatever the prefetch policy asks for
+    /// around the current selection.
+    fn load_selected(&mut self, fetcher: &Fetcher, tab: &TabState) {
+        let selected = self.list_for(tab).selected_thread as usize;
+        if *tab == TabState::Bookmarks && self.tab == TabState::Bookmarks {
+            if let Some(over) = self.bookmarks.list.selected() {
+                let url = over.url.clone();
+                self.bookmarks.mark_seen(url.as_str());
             }
         }
+        if *tab == self.tab {
+            self.mark_read();
+        }
+        self.load_thread(selected, fetcher, tab);
+
+        let ahead = self.prefetch.threads as usize;
+        let following: Vec<usize> = self
+            .list_for(tab)
+            .shown()
+            .into_iter()
+            .skip_while(|&n| n != selected)
+            .skip(1)
+            .take(ahead)
+            .collect();
+        for n in following {
+            self.load_thread(n, fetcher, tab);
+        }
+        let list = self.list_for(tab);
+        let paginated = *tab != TabState::Bookmarks && !list.complete;
+        if ahead > 0 && paginated && selected + ahead >= list.overview.len() {
+            let request = list.next_page();
+            self.request(fetcher, tab, request);
+        }
+
+        let list = self.list_for(tab);
+        let Some(t) = list.selected_data() else {
+            return;
+        };
+        let remaining = t
+            .comments
+            .len()
+            .saturating_sub(t.selected_comment as usize + 1);
+        // A position restored from an earlier visit may be past the loaded
+        // comments
+        let missing = t.selected_comment as usize >= t.comments.len();
+        let wanted = remaining < self.prefetch.comments as usize || missing;
+        if t.comment_page > 0 && !t.complete && wanted {
+            let request = Request::Thread {
+                thread: list.overview[selected].clone(),
+                page: t.comment_page + 1,
+                revalidate: false,
+            };
+            self.request(fetcher, tab, request);
+        }
     }
 
-    pub fn clean_comments(
-        &mut self,
-        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<(), Box<dyn Error>> {
-        let thread = self
-            .data
-            .data
-            .get_mut(self.selected_thread as usize)
-            .unwrap();
-        thread.comments.clear();
-        self.data.selected_comment = 0 - 1;
-        thread.comment_page = 0;
-        self.next_comment(terminal)?;
+    pub fn fail(&mut self, error: Error, retry: Option<(TabState, Request)>) {
+        self.error = Some(ErrorPopup {
+            message: error.to_string(),
+            retry,
+        });
+    }
+
+    fn dismiss_error(&mut self) -> Result<(), Error> {
+        self.error = None;
         return Ok(());
     }
 
-    fn next_thread(
-        &mut self,
-        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-        tab: &TabState,
-    ) -> Result<(), Box<dyn Error>> {
-        if *tab == TabState::Bookmarks && self.overview.is_empty() {
+    fn retry(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        if let Some((tab, request)) = self.error.take().and_then(|e| e.retry) {
+            self.request(fetcher, &tab, request);
+        }
+        return Ok(());
+    }
+
+    /// Whether the outcome of the request affects what is on screen.
+    fn is_visible(&self, tab: &TabState, request: &Request) -> bool {
+        if *tab != self.tab {
+            return false;
+        }
+        let list = self.list_for(tab);
+        return match request {
+            Request::Threads { .. } => list.overview.is_empty(),
+            Request::Thread { thread, .. } => list.selected().is_some_and(|o| o.url == thread.url),
+            Request::Categories => self.category_picker.is_some(),
+        };
+    }
+
+    /// Forgets what was waiting on a request that won't complete.
+    fn abandon(&mut self, tab: &TabState, request: &Request) {
+        let Request::Thread { thread, .. } = request else {
+            return;
+        };
+        if *tab == TabState::Bookmarks {
+            self.bookmarks.stop_refreshing(thread.url.as_str());
+        }
+        let key = (*tab, thread.url.clone());
+        self.loading_all.retain(|k| *k != key);
+        if self.is_visible(tab, request) {
+            self.seeking_match = false;
+        }
+    }
+
+    /// Integrates the result of a request sent through the fetcher for `tab`.
+    pub fn apply(&mut self, response: Response, fetcher: &Fetcher, tab: &TabState) {
+        self.pending
+            .retain(|(t, r)| !(t == tab && *r == response.request));
+        let payload = match response.result {
+            Ok(p) => p,
+            Err(e) => {
+                self.abandon(tab, &response.request);
+                // Offline, missing prefetched pages are expected
+                if matches!(e, Error::NotCached(_)) && !self.is_visible(tab, &response.request) {
+                    return;
+                }
+                self.fail(e, Some((*tab, response.request)));
+                return;
+            }
+        };
+
+        let list = self.list_mut(tab);
+        match (response.request, payload) {
+            (Request::Threads { source, page }, Payload::Threads(mut threads, cached_at)) => {
+                // Pages of a list that was since replaced are dropped
+                if page != list.overview_page + 1 || source != list.source {
+                    return;
+                }
+                threads.retain(|t| !list.overview.iter().any(|o| o.url == t.url));
+                if threads.is_empty() {
+                    list.complete = true;
+                    return;
+                }
+                list.overview_page = page;
+                list.overview_cached_at = oldest(list.overview_cached_at, cached_at);
+                for t in threads {
+                    let data = ThreadData {
+                        title: t.title.clone(),
+                        ..Default::default()
+                    };
+                    list.push(t, data);
+                }
+                self.load_selected(fetcher, tab);
+            }
+            (Request::Thread { thread, page, .. }, Payload::Thread(mut data)) => {
+                let Some(n) = list.overview.iter().position(|x| x.url == thread.url) else {
+                    return;
+                };
+                let selected = n == list.selected_thread as usize;
+                let t = list.threads.get_mut(n).unwrap();
+                // Refreshing fetches the last loaded page again since that's
+                // where new comments show up
+                let reload = page > 0 && page == t.comment_page;
+                if page != t.comment_page + 1 && !reload {
+                    return;
+                }
+                // Past the last page the forum either returns nothing or
+                // the last page again
+                data.comments.retain(|c| {
+                    !t.comments
+                        .iter()
+                        .any(|o| o.author == c.author && o.date == c.date)
+                });
+                let start = t.comments.len();
+                let added = data.comments.clone();
+                if !data.comments.is_empty() {
+                    t.comment_page = page;
+                    t.comments.append(&mut data.comments);
+                    t.cached_at = oldest(t.cached_at, data.cached_at);
+                } else if !reload {
+                    t.complete = true;
+                    let last = t.comments.len().saturating_sub(1) as u16;
+                    if t.selected_comment > last {
+                        t.selected_comment = last;
+                        t.viewer_scroll = 0;
+                    }
+                }
+
+                let (complete, count, loaded) = (t.complete, t.comments.len(), t.comment_page);
+                self.index.add_comments(&thread, start, &added);
+                if *tab == TabState::Bookmarks {
+                    self.bookmark_loaded(fetcher, &thread, complete, count, loaded);
+                }
+                let key = (*tab, thread.url.clone());
+                if self.loading_all.contains(&key) {
+                    if complete {
+                        self.loading_all.retain(|k| *k != key);
+                    } else {
+                        let request = Request::Thread {
+                            thread,
+                            page: loaded + 1,
+                            revalidate: false,
+                        };
+                        self.request(fetcher, tab, request);
+                    }
+                }
+                if selected {
+                    self.load_selected(fetcher, tab);
+                    if self.seeking_match && *tab == self.tab {
+                        self.seeking_match = false;
+                        self.jump_to_match(fetcher, true, false);
+                    }
+                }
+            }
+            (Request::Categories, Payload::Categories(categories)) => {
+                self.categories = categories;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn clean_comments(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let tab = self.tab;
+        let list = self.list_mut(&tab);
+        let Some(thread) = list.selected_data_mut() else {
+            return Ok(());
+        };
+        *thread = ThreadData {
+            title: thread.title.clone(),
+            ..Default::default()
+        };
+        self.load_selected(fetcher, &tab);
+        return Ok(());
+    }
+
+    /// Moves the selection among the shown threads, a hidden selected
+    /// thread is left for the first shown one.
+    fn move_thread(&mut self, fetcher: &Fetcher, up: bool) -> Result<(), Error> {
+        let tab = self.tab;
+        let list = self.list_mut(&tab);
+        let shown = list.shown();
+        let selected = list.selected_thread as usize;
+        let target = match shown.iter().position(|&n| n == selected) {
+            Some(p) if up => p.checked_sub(1).map(|p| shown[p]),
+            Some(p) => shown.get(p + 1).copied(),
+            None => shown.first().copied(),
+        };
+        let Some(target) = target else {
+            if !up && tab != TabState::Bookmarks && !list.complete {
+                let request = list.next_page();
+                self.request(fetcher, &tab, request);
+            }
+            return Ok(());
+        };
+
+        // Loaded by `update` once every step of a multiplier is done
+        list.selected_thread = target as u16;
+        return Ok(());
+    }
+
+    fn next_comment(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let tab = self.tab;
+        let list = self.list_mut(&tab);
+        let selected = list.selected_thread as usize;
+        if !list.is_shown(selected) {
             return Ok(());
         }
+        let Some(t) = list.threads.get_mut(selected) else {
+            return Ok(());
+        };
+        if t.comment_page == 0 {
+            return Ok(());
+        }
+        if (t.selected_comment as usize + 1) < t.comments.len() {
+            t.selected_comment += 1;
+            t.viewer_scroll = 0;
+        } else if !t.complete {
+            let request = Request::Thread {
+                thread: list.overview[selected].clone(),
+                page: t.comment_page + 1,
+                revalidate: false,
+            };
+            self.request(fetcher, &tab, request);
+        }
+        self.load_selected(fetcher, &tab);
+        return Ok(());
+    }
 
-        self.selected_thread += 1;
-        while *tab == TabState::Home && self.selected_thread as usize >= self.overview.len() {
-            self.overview_page += 1;
-            let mut new_overviews =
-                get_threads(&self.http_client, self.overview_page, terminal, true)?;
-            self.overview.append(&mut new_overviews);
+    fn prev_comment(&mut self) -> Result<(), Error> {
+        let tab = self.tab;
+        let Some(t) = self.list_mut(&tab).selected_data_mut() else {
+            return Ok(());
+        };
+        if t.selected_comment == 0 {
+            return Ok(());
         }
+        t.selected_comment -= 1;
+        t.viewer_scroll = 0;
+        return Ok(());
+    }
 
-        if self.selected_thread as usize >= self.overview.len() {
-            self.selected_thread = self.overview.len() as u16 - 1;
+    fn scroll_down(&mut self) -> Result<(), Error> {
+        let tab = self.tab;
+        if let Some(t) = self.list_mut(&tab).selected_data_mut() {
+            t.viewer_scroll += 1;
         }
+        return Ok(());
+    }
 
-        self.viewer_scroll = 0;
-        self.data.selected_comment = 0;
-        if self.selected_thread as usize >= self.data.data.len() {
-            let t_over = self.overview.get(self.selected_thread as usize).unwrap();
-            let mut t = get_thread(&self.http_client, t_over, 1, terminal, true)?;
-            t.comment_page = 1;
-            self.data.data.push(t);
+    fn scroll_up(&mut self) -> Result<(), Error> {
+        let tab = self.tab;
+        if let Some(t) = self.list_mut(&tab).selected_data_mut() {
+            t.viewer_scroll = t.viewer_scroll.saturating_sub(1);
         }
+        return Ok(());
+    }
 
-        let t = self
-            .data
-            .data
-            .get_mut(self.selected_thread as usize)
-            .unwrap();
-        while self.data.selected_comment as usize >= t.comments.len() {
-            t.comment_page += 1;
-            let t_over = self.overview.get(self.selected_thread as usize).unwrap();
-            let mut new_comments =
-                get_thread(&self.http_client, t_over, t.comment_page, terminal, true)?;
-            t.comments.append(&mut new_comments.comments);
+    /// Records the selected comment of the current tab as read.
+    fn mark_read(&mut self) {
+        let list = self.list();
+        let (Some(over), Some(t)) = (list.selected(), list.selected_data()) else {
+            return;
+        };
+        let comment = t.selected_comment as usize;
+        if let Some(date) = t.comments.get(comment).map(|c| c.date) {
+            let url = over.url.clone();
+            self.reads.mark_read(url.as_str(), comment, date);
         }
+    }
+
+    /// Whether the `n`th thread of the current tab has comments the user
+    /// hasn't read since their last visit.
+    pub fn has_unread(&self, n: usize) -> bool {
+        let list = self.list();
+        let Some(over) = list.overview.get(n) else {
+            return false;
+        };
+        let loaded = list.threads.get(n).map_or(0, |t| t.comments.len());
+        let counted = match self.tab {
+            TabState::Bookmarks => self
+                .bookmarks
+                .get(over.url.as_str())
+                .and_then(|b| b.comments)
+                .unwrap_or(0) as usize,
+            TabState::Home | TabState::Search => 0,
+        };
+        return self
+            .reads
+            .has_unread(over.url.as_str(), loaded.max(counted));
+    }
 
+    /// Selects the closest comment of the selected thread matching the
+    /// search, the selected one included if `inclusive`. Going forward past
+    /// the loaded comments fetches the next page, the search wraps around
+    /// once the thread is complete.
+    fn jump_to_match(&mut self, fetcher: &Fetcher, forward: bool, inclusive: bool) {
+        let Some(query) = self.comment_search.as_ref() else {
+            return;
+        };
+        let tab = self.tab;
+        let list = self.list_for(&tab);
+        let (Some(over), Some(t)) = (list.selected(), list.selected_data()) else {
+            return;
+        };
+        let current = t.selected_comment as usize;
+        let matching: Vec<usize> = t
+            .comments
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| comment_matches(c, query))
+            .map(|(i, _)| i)
+            .collect();
+        let target = if forward {
+            let ahead = matching
+                .iter()
+                .find(|&&i| i > current || (inclusive && i == current));
+            match ahead {
+                Some(&i) => Some(i),
+                None if !t.complete && t.comment_page > 0 => {
+                    let request = Request::Thread {
+                        thread: over.clone(),
+                        page: t.comment_page + 1,
+                        revalidate: false,
+                    };
+                    self.seeking_match = true;
+                    self.request(fetcher, &tab, request);
+                    return;
+                }
+                None => matching.first().copied(),
+            }
+        } else {
+            let behind = matching
+                .iter()
+                .rev()
+                .find(|&&i| i < current || (inclusive && i == current));
+            behind.or(matching.last()).copied()
+        };
+
+        let Some(target) = target else {
+            return;
+        };
+        let t = self.list_mut(&tab).selected_data_mut().unwrap();
+        if t.selected_comment != target as u16 {
+            t.selected_comment = target as u16;
+            t.viewer_scroll = 0;
+        }
+        self.load_selected(fetcher, &tab);
+    }
+
+    fn next_match(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        self.jump_to_match(fetcher, true, false);
         return Ok(());
     }
 
-    fn prev_thread(&mut self) -> Result<(), Box<dyn Error>> {
-        if self.selected_thread == 0 {
+    fn prev_match(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        self.jump_to_match(fetcher, false, false);
+        return Ok(());
+    }
+
+    fn clear_comment_search(&mut self) -> Result<(), Error> {
+        self.comment_search = None;
+        self.seeking_match = false;
+        return Ok(());
+    }
+
+    /// Fetches every remaining page of the selected thread.
+    fn load_all(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let tab = self.tab;
+        let list = self.list_for(&tab);
+        let (Some(over), Some(t)) = (list.selected(), list.selected_data()) else {
+            return Ok(());
+        };
+        if t.complete {
             return Ok(());
         }
-        self.selected_thread -= 1;
-        self.viewer_scroll = 0;
-        self.data.selected_comment = 0;
+        let key = (tab, over.url.clone());
+        let request = Request::Thread {
+            thread: over.clone(),
+            page: t.comment_page + 1,
+            revalidate: false,
+        };
+        if !self.loading_all.contains(&key) {
+            self.loading_all.push(key);
+        }
+        self.request(fetcher, &tab, request);
         return Ok(());
     }
 
-    fn next_comment(
-        &mut self,
-        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<(), Box<dyn Error>> {
-        self.data.selected_comment += 1;
-        self.viewer_scroll = 0;
-        let t = self
-            .data
-            .data
-            .get_mut(self.selected_thread as usize)
-            .unwrap();
-        while self.data.selected_comment as usize >= t.comments.len() {
-            t.comment_page += 1;
-            let t_over = self.overview.get(self.selected_thread as usize).unwrap();
-            let mut new_comments =
-                get_thread(&self.http_client, t_over, t.comment_page, terminal, true)?;
-            t.comments.append(&mut new_comments.comments);
-        }
-        return Ok(());
-    }
-
-    fn prev_comment(&mut self) -> Result<(), Box<dyn Error>> {
-        if self.data.selected_comment == 0 {
+    /// Selects the first comment that wasn't read, loading the next page
+    /// when it's not there yet.
+    fn first_unread(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let tab = self.tab;
+        let list = self.list_for(&tab);
+        let (Some(over), Some(t)) = (list.selected(), list.selected_data()) else {
+            return Ok(());
+        };
+        if t.comments.is_empty() {
             return Ok(());
         }
-        self.data.selected_comment -= 1;
-        self.viewer_scroll = 0;
+        let unread = self.reads.first_unread(over.url.as_str());
+        let target = unread.min(t.comments.len() - 1) as u16;
+        if unread >= t.comments.len() && !t.complete {
+            let request = Request::Thread {
+                thread: over.clone(),
+                page: t.comment_page + 1,
+                revalidate: false,
+            };
+            self.request(fetcher, &tab, request);
+        }
+        let t = self.list_mut(&tab).selected_data_mut().unwrap();
+        if t.selected_comment != target {
+            t.selected_comment = target;
+            t.viewer_scroll = 0;
+        }
+        self.load_selected(fetcher, &tab);
         return Ok(());
     }
 
-    fn scroll_down(&mut self) -> Result<(), Box<dyn Error>> {
-        self.viewer_scroll += 1;
+    fn open_categories(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        if self.tab != TabState::Home {
+            return Ok(());
+        }
+        let selected = match &self.home.source {
+            Source::Category(c) => self.categories.iter().position(|x| x == c),
+            _ => None,
+        };
+        let selected = selected.map_or(0, |n| n + 1);
+        self.category_picker = Some(selected);
+        if self.categories.is_empty() {
+            self.request(fetcher, &TabState::Home, Request::Categories);
+        }
+        return Ok(());
+    }
+
+    fn close_categories(&mut self) -> Result<(), Error> {
+        self.category_picker = None;
         return Ok(());
     }
 
-    fn scroll_up(&mut self) -> Result<(), Box<dyn Error>> {
-        if self.viewer_scroll > 0 {
-            self.viewer_scroll -= 1;
+    fn move_category(&mut self, up: bool) -> Result<(), Error> {
+        let Some(selected) = self.category_picker.as_mut() else {
+            return Ok(());
+        };
+        if up {
+            *selected = selected.saturating_sub(1);
+        } else if *selected < self.categories.len() {
+            *selected += 1;
         }
         return Ok(());
     }
 
-    pub(crate) fn new(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Self {
-        let mut m = Model {
-            ..Default::default()
+    /// Replaces the home threads with those of the selected category,
+    /// which get their own pagination.
+    fn pick_category(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let Some(selected) = self.category_picker.take() else {
+            return Ok(());
         };
-        m.overview = get_threads(&m.http_client, 1, terminal, false).unwrap();
-        m.overview_page = 1;
-        let t = get_thread(
-            &m.http_client,
-            m.overview.get(m.selected_thread as usize).unwrap(),
-            1,
-            terminal,
-            false,
-        )
-        .unwrap();
-        m.data.data.push(t);
-        return m;
+        let source = match selected.checked_sub(1).and_then(|n| self.categories.get(n)) {
+            Some(c) => Source::Category(c.clone()),
+            None => Source::Discussions,
+        };
+        if source != self.home.source {
+            self.browse(fetcher, TabState::Home, source);
+        }
+        return Ok(());
     }
 
-    pub fn new_bookmarks() -> Self {
-        Model {
+    /// Replaces the threads of a tab with those from `source`.
+    fn browse(&mut self, fetcher: &Fetcher, tab: TabState, source: Source) {
+        self.pending
+            .retain(|(t, r)| *t != tab || matches!(r, Request::Categories));
+        let list = self.list_mut(&tab);
+        *list = ThreadList {
+            source,
+            filter: list.filter.take(),
+            sort: list.sort,
             ..Default::default()
+        };
+        let request = list.next_page();
+        self.request(fetcher, &tab, request);
+    }
+
+    fn switch_tab(&mut self, tab: TabState, fetcher: &Fetcher) -> Result<(), Error> {
+        self.tab = tab;
+        self.load_selected(fetcher, &tab);
+        return Ok(());
+    }
+
+    /// Bookmarks the selected thread of a tab other than the bookmarks.
+    fn add_bookmark(&mut self) -> Result<(), Error> {
+        if self.tab == TabState::Bookmarks {
+            return Ok(());
+        }
+        let list = self.list();
+        if let (Some(over), Some(data)) = (list.selected(), list.selected_data()) {
+            let (over, data) = (over.clone(), data.clone());
+            self.index.add_comments(&over, 0, &data.comments);
+            self.bookmarks.add(over, data);
+        }
+        return Ok(());
+    }
+
+    fn remove_bookmark(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let Some(url) = self.list().selected().map(|o| o.url.clone()) else {
+            return Ok(());
+        };
+        self.bookmarks.remove(url.as_str());
+        if self.tab == TabState::Bookmarks {
+            self.load_selected(fetcher, &TabState::Bookmarks);
+        }
+        return Ok(());
+    }
+
+    /// Only possible while the bookmarks are shown in their own order.
+    fn move_bookmark(&mut self, up: bool) -> Result<(), Error> {
+        let list = &self.bookmarks.list;
+        if self.tab == TabState::Bookmarks && list.filter.is_none() && list.sort == SortMode::Listed
+        {
+            self.bookmarks.move_selected(up);
+        }
+        return Ok(());
+    }
+
+    /// Fetches the shown bookmarks again from their last loaded page on.
+    fn refresh_bookmarks(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        for (thread, page) in self.bookmarks.refresh() {
+            let request = Request::Thread {
+                thread,
+                page,
+                revalidate: true,
+            };
+            self.request(fetcher, &TabState::Bookmarks, request);
+        }
+        return Ok(());
+    }
+
+    /// Follows the pages of a bookmark being refreshed, its comments are
+    /// counted once they are all loaded.
+    fn bookmark_loaded(
+        &mut self,
+        fetcher: &Fetcher,
+        thread: &ThreadOverview,
+        complete: bool,
+        count: usize,
+        page: u16,
+    ) {
+        if complete {
+            self.bookmarks.set_count(thread.url.as_str(), count as u32);
+        } else if self.bookmarks.is_refreshing(thread.url.as_str()) {
+            let request = Request::Thread {
+                thread: thread.clone(),
+                page: page + 1,
+                revalidate: true,
+            };
+            self.request(fetcher, &TabState::Bookmarks, request);
+        }
+    }
+
+    /// Opens a prompt filled with the current value of what it edits.
+    fn open_prompt(&mut self, kind: PromptKind) -> Result<(), Error> {
+        let anywhere = matches!(
+            kind,
+            PromptKind::Search
+                | PromptKind::CommentSearch
+                | PromptKind::IndexSearch
+                | PromptKind::ThreadFilter
+        );
+        if self.tab != TabState::Bookmarks && !anywhere {
+            return Ok(());
+        }
+        let bookmark = self
+            .list()
+            .selected()
+            .and_then(|o| self.bookmarks.get(o.url.as_str()));
+        let input = match kind {
+            PromptKind::Folder => match bookmark {
+                Some(b) => b.folder.clone().unwrap_or_default(),
+                None => return Ok(()),
+            },
+            PromptKind::Tags => match bookmark {
+                Some(b) => b.tags.join(" "),
+                None => return Ok(()),
+            },
+            PromptKind::BookmarkFilter => self
+                .bookmarks
+                .filter
+                .as_ref()
+                .map(|f| f.to_string())
+                .unwrap_or_default(),
+            PromptKind::Search => match &self.search.source {
+                Source::Search(query) => query.clone(),
+                _ => String::new(),
+            },
+            PromptKind::CommentSearch => self.comment_search.clone().unwrap_or_default(),
+            PromptKind::IndexSearch => String::new(),
+            PromptKind::ThreadFilter => self
+                .list()
+                .filter
+                .as_ref()
+                .map(|f| f.to_string())
+                .unwrap_or_default(),
+        };
+        self.prompt = Some(Prompt { kind, input });
+        return Ok(());
+    }
+
+    fn prompt_input(&mut self, c: char) -> Result<(), Error> {
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.input.push(c);
+        }
+        return Ok(());
+    }
+
+    fn prompt_backspace(&mut self) -> Result<(), Error> {
+        if let Some(prompt) = self.prompt.as_mut() {
+            prompt.input.pop();
+        }
+        return Ok(());
+    }
+
+    fn prompt_cancel(&mut self) -> Result<(), Error> {
+        self.prompt = None;
+        return Ok(());
+    }
+
+    fn prompt_submit(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let Some(prompt) = self.prompt.take() else {
+            return Ok(());
+        };
+        let input = prompt.input.trim();
+        let url = self.list().selected().map(|o| o.url.clone());
+        match (prompt.kind, url) {
+            (PromptKind::Folder, Some(url)) => {
+                let folder = Some(input.to_string()).filter(|f| !f.is_empty());
+                self.bookmarks.set_folder(url.as_str(), folder);
+            }
+            (PromptKind::Tags, Some(url)) => {
+                let mut tags: Vec<String> = vec![];
+                for tag in input.split_whitespace() {
+                    if !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_string());
+                    }
+                }
+                self.bookmarks.set_tags(url.as_str(), tags);
+            }
+            (PromptKind::BookmarkFilter, _) => self.bookmarks.set_filter(Filter::parse(input)),
+            (PromptKind::CommentSearch, _) => {
+                self.comment_search = Some(input.to_string()).filter(|q| !q.is_empty());
+                self.seeking_match = false;
+                self.jump_to_match(fetcher, true, true);
+                return Ok(());
+            }
+            (PromptKind::IndexSearch, _) => {
+                let query = Query::parse(input);
+                if !query.is_empty() {
+                    self.index_results = Some(IndexResults {
+                        query: input.to_string(),
+                        hits: self.index.search(&query),
+                        selected: 0,
+                    });
+                }
+                return Ok(());
+            }
+            (PromptKind::ThreadFilter, _) => {
+                self.set_thread_filter(fetcher, ThreadFilter::parse(input));
+                return Ok(());
+            }
+            (PromptKind::Search, _) => {
+                if !input.is_empty() {
+                    self.tab = TabState::Search;
+                    self.browse(fetcher, TabState::Search, Source::Search(input.to_string()));
+                }
+                return Ok(());
+            }
+            (PromptKind::Folder | PromptKind::Tags, None) => return Ok(()),
+        }
+        // The bookmarks were changed, the selection may be another thread
+        self.load_selected(fetcher, &TabState::Bookmarks);
+        return Ok(());
+    }
+
+    /// Filters the threads of the current tab, the selection moves to the
+    /// first shown thread if it gets hidden. When none is shown, the hidden
+    /// selection is neither loaded nor displayed.
+    fn set_thread_filter(&mut self, fetcher: &Fetcher, filter: Option<ThreadFilter>) {
+        let tab = self.tab;
+        let list = self.list_mut(&tab);
+        list.filter = filter;
+        let shown = list.shown();
+        if !shown.contains(&(list.selected_thread as usize)) {
+            if let Some(&first) = shown.first() {
+                list.selected_thread = first as u16;
+            }
         }
+        self.load_selected(fetcher, &tab);
     }
 
-    fn add_multiplier(&mut self, n: u32) -> Result<(), Box<dyn Error>> {
+    fn cycle_sort(&mut self) -> Result<(), Error> {
+        let tab = self.tab;
+        let list = self.list_mut(&tab);
+        list.sort = list.sort.next();
+        return Ok(());
+    }
+
+    fn move_hit(&mut self, up: bool) -> Result<(), Error> {
+        let Some(results) = self.index_results.as_mut() else {
+            return Ok(());
+        };
+        if up {
+            results.selected = results.selected.saturating_sub(1);
+        } else if results.selected + 1 < results.hits.len() {
+            results.selected += 1;
+        }
+        return Ok(());
+    }
+
+    fn close_hits(&mut self) -> Result<(), Error> {
+        self.index_results = None;
+        return Ok(());
+    }
+
+    /// Selects the comment of the chosen hit in the tab already showing its
+    /// thread, the search tab is filled with the threads of the hits when
+    /// none does.
+    fn open_hit(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
+        let Some(results) = self.index_results.take() else {
+            return Ok(());
+        };
+        let Some(hit) = results.hits.get(results.selected) else {
+            return Ok(());
+        };
+        let url = hit.thread.url.as_str();
+        let tabs = [
+            self.tab,
+            TabState::Home,
+            TabState::Bookmarks,
+            TabState::Search,
+        ];
+        let shown = tabs.into_iter().find_map(|tab| {
+            let list = self.list_for(&tab);
+            let position = list.overview.iter().position(|o| o.url == url);
+            position
+                .filter(|n| list.shown().contains(n))
+                .map(|n| (tab, n))
+        });
+        let (tab, n) = match shown {
+            Some(shown) => shown,
+            None => {
+                self.pending
+                    .retain(|(t, r)| *t != TabState::Search || matches!(r, Request::Categories));
+                self.search = ThreadList {
+                    source: Source::Index(results.query.clone()),
+                    complete: true,
+                    ..Default::default()
+                };
+                for h in results.hits.iter() {
+                    if !self.search.overview.iter().any(|o| o.url == h.thread.url) {
+                        let data = ThreadData {
+                            title: h.thread.title.clone(),
+                            ..Default::default()
+                        };
+                        self.search.push(h.thread.clone(), data);
+                    }
+                }
+                let n = self.search.overview.iter().position(|o| o.url == url);
+                (TabState::Search, n.unwrap_or(0))
+            }
+        };
+
+        self.tab = tab;
+        let list = self.list_mut(&tab);
+        list.selected_thread = n as u16;
+        if let Some(t) = list.selected_data_mut() {
+            t.selected_comment = hit.comment as u16;
+            t.viewer_scroll = 0;
+        }
+        self.load_selected(fetcher, &tab);
+        return Ok(());
+    }
+
+    pub(crate) fn new(
+        fetcher: &Fetcher,
+        prefetch: Prefetch,
+        bookmarks: Bookmarks,
+        reads: ReadMarkers,
+        index: SearchIndex,
+    ) -> Self {
+        let mut m = Model {
+            tab: TabState::Home,
+            home: ThreadList::default(),
+            bookmarks,
+            // Nothing to fetch before the first search
+            search: ThreadList {
+                complete: true,
+                ..Default::default()
+            },
+            reads,
+            index,
+            index_results: None,
+            categories: vec![],
+            category_picker: None,
+            multiplier: vec![],
+            pending: vec![],
+            error: None,
+            prompt: None,
+            comment_search: None,
+            seeking_match: false,
+            loading_all: vec![],
+            prefetch,
+        };
+        let request = m.home.next_page();
+        m.request(fetcher, &TabState::Home, request);
+        return m;
+    }
+
+    fn add_multiplier(&mut self, n: u32) -> Result<(), Error> {
         self.multiplier.push(n);
         return Ok(());
     }
@@ -224,45 +1189,116 @@ impl Model {
         return ris;
     }
 
-    fn clean_multiplier(&mut self) -> Result<(), Box<dyn Error>> {
+    /// Clears the multiplier and abandons every fetch in progress.
+    fn clean_multiplier(&mut self, fetcher: &Fetcher) -> Result<(), Error> {
         self.multiplier.clear();
+        if !self.pending.is_empty() {
+            fetcher.cancel();
+            self.pending.clear();
+            self.bookmarks.cancel_refresh();
+            self.loading_all.clear();
+            self.seeking_match = false;
+        }
         return Ok(());
     }
 }
 
-pub fn update(
-    model: &mut Model,
-    action: Action,
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    tab: &TabState,
-) -> Result<(), Box<dyn Error>> {
+fn oldest(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    return match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+}
+
+pub fn update(model: &mut Model, action: Action, fetcher: &Fetcher) -> Result<(), Error> {
     match action {
-        Action::Quit | Action::Nothing | Action::Moltiply(_) | Action::Nullify => {
-            let _ = match action {
+        Action::Quit
+        | Action::Nothing
+        | Action::Moltiply(_)
+        | Action::Nullify
+        | Action::DismissError
+        | Action::Retry
+        | Action::SwitchTab(_)
+        | Action::AddBookmark
+        | Action::RemoveBookmark
+        | Action::RefreshBookmarks
+        | Action::FirstUnread
+        | Action::ClearCommentSearch
+        | Action::LoadAll
+        | Action::OpenCategories
+        | Action::CloseCategories
+        | Action::NextCategory
+        | Action::PrevCategory
+        | Action::PickCategory
+        | Action::OpenPrompt(_)
+        | Action::PromptInput(_)
+        | Action::PromptBackspace
+        | Action::PromptSubmit
+        | Action::PromptCancel
+        | Action::NextHit
+        | Action::PrevHit
+        | Action::OpenHit
+        | Action::CloseHits
+        | Action::CycleSort => {
+            return match action {
                 Action::Quit => unreachable!(),
                 Action::Nothing => Ok(()),
                 Action::Moltiply(n) => model.add_multiplier(n),
-                Action::Nullify => model.clean_multiplier(),
+                Action::Nullify => model.clean_multiplier(fetcher),
+                Action::DismissError => model.dismiss_error(),
+                Action::Retry => model.retry(fetcher),
+                Action::SwitchTab(tab) => model.switch_tab(tab, fetcher),
+                Action::AddBookmark => model.add_bookmark(),
+                Action::RemoveBookmark => model.remove_bookmark(fetcher),
+                Action::RefreshBookmarks => model.refresh_bookmarks(fetcher),
+                Action::FirstUnread => model.first_unread(fetcher),
+                Action::ClearCommentSearch => model.clear_comment_search(),
+                Action::LoadAll => model.load_all(fetcher),
+                Action::OpenCategories => model.open_categories(fetcher),
+                Action::CloseCategories => model.close_categories(),
+                Action::NextCategory => model.move_category(false),
+                Action::PrevCategory => model.move_category(true),
+                Action::PickCategory => model.pick_category(fetcher),
+                Action::OpenPrompt(kind) => model.open_prompt(kind),
+                Action::PromptInput(c) => model.prompt_input(c),
+                Action::PromptBackspace => model.prompt_backspace(),
+                Action::PromptSubmit => model.prompt_submit(fetcher),
+                Action::PromptCancel => model.prompt_cancel(),
+                Action::NextHit => model.move_hit(false),
+                Action::PrevHit => model.move_hit(true),
+                Action::OpenHit => model.open_hit(fetcher),
+                Action::CloseHits => model.close_hits(),
+                Action::CycleSort => model.cycle_sort(),
                 _ => Ok(()),
             };
-            return Ok(());
         }
         _ => (),
     };
 
     let mult = model.get_multiplier();
+    let tab = model.tab;
+    let selected = model.list_for(&tab).selected_thread;
 
     for _ in 0..mult {
         match action {
-            Action::NextThread => model.next_thread(terminal, tab),
-            Action::PrevThread => model.prev_thread(),
-            Action::NextComment => model.next_comment(terminal),
+            Action::NextThread => model.move_thread(fetcher, false),
+            Action::PrevThread => model.move_thread(fetcher, true),
+            Action::NextComment => model.next_comment(fetcher),
             Action::PrevComment => model.prev_comment(),
             Action::ScrollDown => model.scroll_down(),
             Action::ScrollUp => model.scroll_up(),
-            Action::CleanComments => model.clean_comments(terminal),
+            Action::CleanComments => model.clean_comments(fetcher),
+            Action::NextMatch => model.next_match(fetcher),
+            Action::PrevMatch => model.prev_match(fetcher),
+            Action::MoveBookmarkUp => model.move_bookmark(true),
+            Action::MoveBookmarkDown => model.move_bookmark(false),
             _ => unreachable!(),
         }?;
     }
+    // Threads passed over on the way aren't fetched
+    let moved = model.list_for(&tab).selected_thread != selected;
+    if matches!(action, Action::NextThread | Action::PrevThread) && moved {
+        model.load_selected(fetcher, &tab);
+    }
     return Ok(());
 }
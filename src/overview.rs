@@ -1,88 +1,332 @@
-use serde::{de::Visitor, Deserialize, Serialize};
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use scraper::ElementRef;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::resource_path,
+    dates::parse_date,
+    html::{attr, select_first, text},
+    search::find_ignore_case,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ThreadOverview {
     pub title: String,
     pub url: String,
     pub author: String,
-    pub replies: String,
+    /// `None` when the forum doesn't show it
+    pub replies: Option<u32>,
+    /// Name of the category, when the listing shows it
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Date of the latest comment according to the listing
+    #[serde(default)]
+    pub last_activity: Option<DateTime<Utc>>,
 }
+
+/// Threads are told apart by their discussion, titles may repeat.
 impl PartialEq for ThreadOverview {
     fn eq(&self, other: &Self) -> bool {
-        self.title == other.title
+        self.url == other.url
     }
 }
 
-#[derive(Deserialize, Debug, Default)]
-pub struct XMLAuthor {
-    #[serde(rename = "@title")]
+/// A subforum, its discussions are listed at `url`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
     pub name: String,
+    /// Path relative to the forum root
+    pub url: String,
 }
 
-#[derive(Deserialize, Debug, Default)]
-pub struct XMLReplies {
-    #[serde(rename = "$text")]
-    pub replies: String,
+/// Where a list of threads comes from.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Source {
+    /// Latest discussions of the whole forum
+    #[default]
+    Discussions,
+    Category(Category),
+    /// Results of a forum search for the query
+    Search(String),
+    /// Threads of the offline index matching the query
+    Index(String),
 }
 
-#[derive(Deserialize, Debug, Default)]
-pub struct XMLTitle {
-    #[serde(rename = "@href")]
-    pub href: String,
-    #[serde(rename = "$text")]
-    pub title: String,
+impl Category {
+    /// Extracts a category from a link, returns `None` unless it points to
+    /// the root of a category.
+    pub fn from_link(link: ElementRef) -> Option<Self> {
+        let url = resource_path(attr(Some(link), "href").as_str());
+        let name = text(Some(link));
+        let mut segments = url.split('/');
+        let is_category = segments.next() == Some("categories")
+            && segments.next().is_some_and(|s| !s.is_empty())
+            && segments.next().is_none();
+        if !is_category || name.is_empty() {
+            return None;
+        }
+        return Some(Category { name, url });
+    }
 }
 
-#[derive(Deserialize, Debug, Default)]
-pub struct XMLTitleWrapper {
-    #[serde(rename = "a")]
-    pub value: XMLTitle,
+/// Path of the discussion `href` points to. Only links of the form
+/// `discussion/<id>/<slug>` qualify, comments also have permalinks as
+/// `discussion/comment/<id>`.
+fn discussion_path(href: &str) -> Option<String> {
+    let href = resource_path(href);
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    let segments: Vec<&str> = href.split('/').take(3).collect();
+    let [kind, id, slug] = segments[..] else {
+        return None;
+    };
+    let valid = kind == "discussion" && id.bytes().all(|b| b.is_ascii_digit());
+    if !valid || slug.is_empty() {
+        return None;
+    }
+    return Some(segments.join("/"));
 }
 
-#[derive(Default, Debug)]
-pub struct XMLDiscussion {
-    pub author: XMLAuthor,
-    pub replies: XMLReplies,
-    pub title: XMLTitleWrapper,
-}
+impl ThreadOverview {
+    /// Builds an overview out of a link to a discussion found in search
+    /// results, the author isn't known there.
+    pub fn from_search_link(link: ElementRef) -> Option<Self> {
+        let url = discussion_path(attr(Some(link), "href").as_str())?;
+        let title = text(Some(link));
+        if title.is_empty() {
+            return None;
+        }
+        return Some(ThreadOverview {
+            title,
+            url,
+            author: String::new(),
+            replies: None,
+            category: None,
+            last_activity: None,
+        });
+    }
 
-impl<'de> Deserialize<'de> for XMLDiscussion {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        deserializer.deserialize_map(DiscussionVisitor {})
+    /// Extracts a discussion from an overview `Item`, returns `None` if it
+    /// doesn't link to a thread.
+    pub fn from_html(item: ElementRef) -> Option<Self> {
+        let descendants = || item.descendants().filter_map(ElementRef::wrap);
+        let (title, url) = descendants()
+            .filter(|e| e.value().name() == "a")
+            .find_map(|a| Some((a, discussion_path(attr(Some(a), "href").as_str())?)))?;
+        let author = select_first(item, "a[title]");
+        // The reply count is the first number shown ahead of the title
+        let ahead: Vec<String> = item
+            .descendants()
+            .take_while(|n| n.id() != title.id())
+            .filter_map(|n| n.value().as_text().map(|t| t.to_string()))
+            .collect();
+        let category = descendants()
+            .filter(|e| e.value().name() == "a")
+            .find_map(Category::from_link)
+            .map(|c| c.name);
+        let last_activity = descendants()
+            .filter(|e| e.value().name() == "time")
+            .filter_map(|t| parse_date(attr(Some(t), "datetime").as_str()))
+            .max();
+        return Some(ThreadOverview {
+            title: text(Some(title)),
+            url,
+            author: attr(author, "title"),
+            replies: parse_count(ahead.join(" ").as_str()),
+            category,
+            last_activity,
+        });
     }
 }
 
-struct DiscussionVisitor {}
-impl<'de> Visitor<'de> for DiscussionVisitor {
-    type Value = XMLDiscussion;
+/// Reads the first count such as `1,234` or `1.2K` found in `text`.
+pub fn parse_count(text: &str) -> Option<u32> {
+    return text.split_whitespace().find_map(|word| {
+        let word = word.replace(',', "");
+        let (number, factor) = match word.strip_suffix(['k', 'K']) {
+            Some(n) => (n, 1_000.0),
+            None => match word.strip_suffix(['m', 'M']) {
+                Some(n) => (n, 1_000_000.0),
+                None => (word.as_str(), 1.0),
+            },
+        };
+        if !number.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let count: f64 = number.parse().ok()?;
+        return Some((count * factor).round() as u32);
+    });
+}
+
+/// Restricts the threads shown in a tab, every criterion has to match.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThreadFilter {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub min_replies: Option<u32>,
+}
 
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("Could not deserialize discussion")
+impl ThreadFilter {
+    /// `author:name` and `replies:n` set those criteria, the other words are
+    /// looked for together in the title. Empty input means no filter.
+    pub fn parse(input: &str) -> Option<Self> {
+        let mut filter = ThreadFilter::default();
+        let mut title = vec![];
+        for word in input.split_whitespace() {
+            if let Some(author) = word.strip_prefix("author:") {
+                filter.author = Some(author.to_string()).filter(|a| !a.is_empty());
+            } else if let Some(n) = word.strip_prefix("replies:").and_then(|n| n.parse().ok()) {
+                filter.min_replies = Some(n);
+            } else {
+                title.push(word);
+            }
+        }
+        filter.title = Some(title.join(" ")).filter(|t| !t.is_empty());
+        if filter == ThreadFilter::default() {
+            return None;
+        }
+        return Some(filter);
     }
 
-    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
-    where
-        A: serde::de::MapAccess<'de>,
-    {
-        let mut data = XMLDiscussion {
-            ..Default::default()
+    pub fn matches(&self, thread: &ThreadOverview) -> bool {
+        let contains = |text: &str, part: &Option<String>| {
+            part.as_ref()
+                .is_none_or(|p| !find_ignore_case(text, p).is_empty())
         };
-        let mut counter = 0;
-
-        while let Some(key) = map.next_key::<Option<&str>>()? {
-            match key.unwrap() {
-                "a" => data.author = map.next_value::<XMLAuthor>()?,
-                "div" if counter == 0 => {
-                    data.replies = map.next_value::<XMLReplies>()?;
-                    counter += 1;
-                }
-                "div" => data.title = map.next_value::<XMLTitleWrapper>()?,
-                _ => (),
-            }
+        let replies = self
+            .min_replies
+            .is_none_or(|min| thread.replies.is_some_and(|r| r >= min));
+        return contains(&thread.title, &self.title)
+            && contains(&thread.author, &self.author)
+            && replies;
+    }
+}
+
+impl Display for ThreadFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if let Some(title) = self.title.as_ref() {
+            parts.push(title.clone());
         }
-        return Ok(data);
+        if let Some(author) = self.author.as_ref() {
+            parts.push(format!("author:{}", author));
+        }
+        if let Some(min) = self.min_replies {
+            parts.push(format!("replies:{}", min));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Order of the threads shown in a tab.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SortMode {
+    /// As the forum lists them, or as the user arranged the bookmarks
+    #[default]
+    Listed,
+    /// Most recent loaded comment first
+    Activity,
+    /// Most replies first
+    Replies,
+    Author,
+    Title,
+}
+
+impl SortMode {
+    pub fn next(self) -> Self {
+        return match self {
+            SortMode::Listed => SortMode::Activity,
+            SortMode::Activity => SortMode::Replies,
+            SortMode::Replies => SortMode::Author,
+            SortMode::Author => SortMode::Title,
+            SortMode::Title => SortMode::Listed,
+        };
+    }
+
+    pub fn label(self) -> &'static str {
+        return match self {
+            SortMode::Listed => "listed",
+            SortMode::Activity => "activity",
+            SortMode::Replies => "replies",
+            SortMode::Author => "author",
+            SortMode::Title => "title",
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::*;
+    use crate::html::elements_with_attr_value;
+
+    fn item(html: &str) -> Option<ThreadOverview> {
+        let document = Html::parse_document(html);
+        let item = elements_with_attr_value(&document, "Item").next();
+        return ThreadOverview::from_html(item.unwrap());
+    }
+
+    #[test]
+    fn from_html_reads_an_item() {
+        let thread = item(
+            r#"<ul><li class="Item">
+                <a title="Ann" href="/profile/Ann"><img src="a.png"></a>
+                <div>1.2K</div>
+                <div><a href="https://thephilosophyforum.com/discussion/42/free-will">Free will</a></div>
+                <div><a href="/categories/ethics">Ethics</a> <time datetime="2024-03-01T10:00:00Z">Mar 1</time></div>
+            </li></ul>"#,
+        )
+        .unwrap();
+        assert_eq!(thread.title, "Free will");
+        assert_eq!(thread.url, "discussion/42/free-will");
+        assert_eq!(thread.author, "Ann");
+        assert_eq!(thread.replies, Some(1200));
+        assert_eq!(thread.category.as_deref(), Some("Ethics"));
+        assert_eq!(thread.last_activity, parse_date("2024-03-01T10:00:00Z"));
+    }
+
+    #[test]
+    fn from_html_survives_malformed_markup() {
+        let thread = item(
+            r#"<li class="Item"><a title="Bob &amp; Co"><img src="b.png">
+                <span>7 <br> comments</b>
+                <p><a href="/discussion/7/a-b">A &lt;b&gt; &bogus; c"#,
+        )
+        .unwrap();
+        assert_eq!(thread.title, "A <b> &bogus; c");
+        assert_eq!(thread.url, "discussion/7/a-b");
+        assert_eq!(thread.author, "Bob & Co");
+        assert_eq!(thread.replies, Some(7));
+    }
+
+    #[test]
+    fn from_html_skips_items_without_a_discussion() {
+        let html = r#"<li class="Item"><a title="Ann" href="/profile/Ann">Ann</a>
+            <a href="/discussion/comment/5">Comment</a></li>"#;
+        assert!(item(html).is_none());
+    }
+
+    #[test]
+    fn parse_count_reads_plain_and_grouped_numbers() {
+        assert_eq!(parse_count("12"), Some(12));
+        assert_eq!(parse_count("1,234"), Some(1234));
+        assert_eq!(parse_count("1,234 comments"), Some(1234));
+    }
+
+    #[test]
+    fn parse_count_reads_suffixes() {
+        assert_eq!(parse_count("1.2K"), Some(1200));
+        assert_eq!(parse_count("3k"), Some(3000));
+        assert_eq!(parse_count("1.5M"), Some(1_500_000));
+    }
+
+    #[test]
+    fn parse_count_skips_words_without_a_number() {
+        assert_eq!(parse_count("Comments: 7"), Some(7));
+        assert_eq!(parse_count("K"), None);
+        assert_eq!(parse_count(".5K"), None);
+        assert_eq!(parse_count(""), None);
     }
 }
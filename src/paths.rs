@@ -0,0 +1,82 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use uzers::{get_current_uid, get_user_by_uid, os::unix::UserExt};
+
+const APP_DIR: &str = "oxi-phil";
+
+/// Where the client keeps its files, following the XDG base directory
+/// specification: bookmarks are data, fetched pages are cache.
+#[derive(Debug, Clone, Default)]
+pub struct Paths {
+    pub config: PathBuf,
+    pub data: PathBuf,
+    pub cache: PathBuf,
+}
+
+impl Paths {
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        let home = home_dir().ok_or("Couldn't determine the home directory")?;
+        return Ok(Paths {
+            config: xdg_dir("XDG_CONFIG_HOME", &home, ".config"),
+            data: xdg_dir("XDG_DATA_HOME", &home, ".local/share"),
+            cache: xdg_dir("XDG_CACHE_HOME", &home, ".cache"),
+        });
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        return self.config.join("config");
+    }
+
+    pub fn bookmarks(&self) -> PathBuf {
+        return self.data.join("bookmarks.xz");
+    }
+
+    pub fn reads(&self) -> PathBuf {
+        return self.data.join("read.cbor");
+    }
+
+    pub fn index(&self) -> PathBuf {
+        return self.cache.join("index.cbor");
+    }
+
+    pub fn pages(&self) -> PathBuf {
+        return self.cache.join("pages");
+    }
+
+    /// Creates the data and cache directories and moves bookmarks saved by
+    /// older versions, which lived in the cache, into the data directory.
+    pub fn prepare(&self) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.data)?;
+        fs::create_dir_all(self.pages())?;
+
+        let legacy = home_dir().map(|h| h.join(".cache").join(APP_DIR).join("bookmarks.txt"));
+        if let Some(legacy) = legacy.filter(|l| l.exists()) {
+            if !self.bookmarks().exists() {
+                fs::copy(&legacy, self.bookmarks())?;
+                fs::remove_file(&legacy)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    if let Some(home) = env::var_os("HOME").filter(|h| !h.is_empty()) {
+        return Some(PathBuf::from(home));
+    }
+    return get_user_by_uid(get_current_uid()).map(|u| u.home_dir().to_path_buf());
+}
+
+/// Relative values are invalid according to the specification and ignored.
+fn xdg_dir(var: &str, home: &Path, fallback: &str) -> PathBuf {
+    let base = env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| home.join(fallback));
+    return base.join(APP_DIR);
+}
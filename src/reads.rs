@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Threads tracked at most, those whose latest read comment is the oldest
+/// are forgotten first.
+const MAX_THREADS: usize = 5000;
+
+/// How far each thread has been read, keyed by its URL. Comments only get
+/// appended to a thread, so everything before the marker has been seen.
+#[derive(Default, Serialize, Deserialize)]
+pub struct ReadMarkers {
+    threads: HashMap<String, u32>,
+    /// Date of the latest comment read in each thread, it still tells which
+    /// comments are new when older ones get deleted
+    #[serde(default)]
+    latest: HashMap<String, DateTime<Utc>>,
+    /// Changed since it was last written to disk
+    #[serde(skip)]
+    pub unsaved: bool,
+}
+
+impl ReadMarkers {
+    /// Index of the first comment that wasn't read, 0 for unvisited threads.
+    pub fn first_unread(&self, url: &str) -> usize {
+        return self.threads.get(url).copied().unwrap_or(0) as usize;
+    }
+
+    pub fn is_unread(&self, url: &str, comment: usize, date: Option<DateTime<Utc>>) -> bool {
+        if let (Some(date), Some(latest)) = (date, self.latest.get(url)) {
+            return date > *latest;
+        }
+        return comment >= self.first_unread(url);
+    }
+
+    /// Whether a thread that was already visited has more than the read
+    /// comments.
+    pub fn has_unread(&self, url: &str, comments: usize) -> bool {
+        return self
+            .threads
+            .get(url)
+            .is_some_and(|&read| comments > read as usize);
+    }
+
+    pub fn mark_read(&mut self, url: &str, comment: usize, date: Option<DateTime<Utc>>) {
+        if !self.threads.contains_key(url) && self.threads.len() >= MAX_THREADS {
+            self.forget_oldest();
+        }
+        let read = self.threads.entry(url.to_string()).or_default();
+        if comment as u32 >= *read {
+            *read = comment as u32 + 1;
+            self.unsaved = true;
+        }
+        if let Some(date) = date {
+            if self.latest.get(url).is_none_or(|&latest| date > latest) {
+                self.latest.insert(url.to_string(), date);
+                self.unsaved = true;
+            }
+        }
+    }
+
+    /// Threads without a known date go first.
+    fn forget_oldest(&mut self) {
+        let oldest = self
+            .threads
+            .keys()
+            .min_by_key(|url| self.latest.get(*url))
+            .cloned();
+        if let Some(url) = oldest {
+            self.threads.remove(&url);
+            self.latest.remove(&url);
+        }
+    }
+}
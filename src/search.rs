@@ -0,0 +1,79 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use crate::{document::plain_text, thread::ThreadComment};
+
+/// Length in bytes of the prefix of `text` matching `needle`, which is
+/// lowercase.
+fn match_len(text: &str, needle: &[char]) -> Option<usize> {
+    let mut wanted = needle.iter().peekable();
+    for (i, c) in text.char_indices() {
+        for lower in c.to_lowercase() {
+            if wanted.next() != Some(&lower) {
+                return None;
+            }
+        }
+        if wanted.peek().is_none() {
+            return Some(i + c.len_utf8());
+        }
+    }
+    return None;
+}
+
+/// Byte ranges of `haystack` matching `needle`, ignoring case.
+pub fn find_ignore_case(haystack: &str, needle: &str) -> Vec<(usize, usize)> {
+    let needle: Vec<char> = needle.chars().flat_map(char::to_lowercase).collect();
+    let mut result = vec![];
+    if needle.is_empty() {
+        return result;
+    }
+    let mut from = 0;
+    for (start, _) in haystack.char_indices() {
+        if start < from {
+            continue;
+        }
+        if let Some(len) = match_len(&haystack[start..], &needle) {
+            result.push((start, start + len));
+            from = start + len;
+        }
+    }
+    return result;
+}
+
+pub fn comment_matches(comment: &ThreadComment, query: &str) -> bool {
+    return !find_ignore_case(comment.author.as_str(), query).is_empty()
+        || !find_ignore_case(plain_text(&comment.text).as_str(), query).is_empty();
+}
+
+/// Restyles the parts of `lines` matching `query`. Matches spanning two
+/// differently styled parts of a line aren't found.
+pub fn highlight(lines: Vec<Line<'static>>, query: &str) -> Vec<Line<'static>> {
+    return lines
+        .into_iter()
+        .map(|line| {
+            let mut spans = vec![];
+            for span in line.spans {
+                let content = span.content.to_string();
+                let mut last = 0;
+                for (start, end) in find_ignore_case(content.as_str(), query) {
+                    if start > last {
+                        spans.push(Span::styled(content[last..start].to_string(), span.style));
+                    }
+                    let style = span.style.patch(match_style());
+                    spans.push(Span::styled(content[start..end].to_string(), style));
+                    last = end;
+                }
+                if last < content.len() {
+                    spans.push(Span::styled(content[last..].to_string(), span.style));
+                }
+            }
+            Line::from(spans)
+        })
+        .collect();
+}
+
+pub fn match_style() -> Style {
+    return Style::default().fg(Color::Black).bg(Color::Yellow);
+}
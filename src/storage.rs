@@ -0,0 +1,759 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use xz::{read::XzDecoder, write::XzEncoder};
+
+use crate::{
+    api::resource_path,
+    bookmarks::{Bookmark, Bookmarks},
+    error::Error,
+    index::{IndexedComment, IndexedThread, SearchIndex},
+    overview::{parse_count, ThreadOverview},
+    reads::ReadMarkers,
+};
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    return PathBuf::from(name);
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    return with_suffix(path, ".bak");
+}
+
+/// Replaces the content of `path` so that a crash at any point leaves either
+/// the old or the new file in place. The previous content is kept as a
+/// backup next to it.
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let tmp = with_suffix(path, ".tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    if path.exists() {
+        let backup_tmp = with_suffix(path, ".bak.tmp");
+        fs::copy(path, &backup_tmp)?;
+        fs::rename(&backup_tmp, backup_path(path))?;
+    }
+    fs::rename(&tmp, path)?;
+
+    // Persist the rename itself
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    return Ok(());
+}
+
+/// Identifies a bookmarks file, followed by the format version as a little
+/// endian `u16` and the xz compressed CBOR payload.
+const MAGIC: &[u8] = b"OXPHBM";
+const VERSION: u16 = 6;
+
+/// On-disk form of the bookmarks. It is kept apart from the structures used
+/// while browsing so that those can change freely: any change to it needs a
+/// new version and a migration from the previous one.
+#[derive(Serialize, Deserialize)]
+struct BookmarksV6 {
+    threads: Vec<BookmarkV6>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BookmarkV6 {
+    title: String,
+    /// Path relative to the forum root
+    url: String,
+    author: String,
+    replies: Option<u32>,
+    folder: Option<String>,
+    tags: Vec<String>,
+    /// Comments counted when the thread was last entirely loaded
+    comments: Option<u32>,
+    /// Comments counted when the bookmark was last opened
+    seen: u32,
+    selected_comment: u16,
+    viewer_scroll: u16,
+    category: Option<String>,
+    last_activity: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct BookmarksV5 {
+    threads: Vec<BookmarkV5>,
+}
+
+#[derive(Deserialize)]
+struct BookmarkV5 {
+    title: String,
+    url: String,
+    author: String,
+    replies: Option<u32>,
+    folder: Option<String>,
+    tags: Vec<String>,
+    comments: Option<u32>,
+    seen: u32,
+    selected_comment: u16,
+    viewer_scroll: u16,
+}
+
+#[derive(Deserialize)]
+struct BookmarksV4 {
+    threads: Vec<BookmarkV4>,
+}
+
+#[derive(Deserialize)]
+struct BookmarkV4 {
+    title: String,
+    url: String,
+    author: String,
+    replies: String,
+    folder: Option<String>,
+    tags: Vec<String>,
+    comments: Option<u32>,
+    seen: u32,
+    selected_comment: u16,
+    viewer_scroll: u16,
+}
+
+#[derive(Deserialize)]
+struct BookmarksV3 {
+    threads: Vec<BookmarkV3>,
+}
+
+#[derive(Deserialize)]
+struct BookmarkV3 {
+    title: String,
+    url: String,
+    author: String,
+    replies: String,
+    folder: Option<String>,
+    tags: Vec<String>,
+    comments: Option<u32>,
+    seen: u32,
+}
+
+#[derive(Deserialize)]
+struct BookmarksV2 {
+    threads: Vec<BookmarkV2>,
+}
+
+#[derive(Deserialize)]
+struct BookmarkV2 {
+    title: String,
+    url: String,
+    author: String,
+    replies: String,
+    folder: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BookmarksV1 {
+    threads: Vec<BookmarkV1>,
+}
+
+#[derive(Deserialize)]
+struct BookmarkV1 {
+    title: String,
+    url: String,
+    author: String,
+    replies: String,
+}
+
+/// Files written before the format was versioned are a dump of the whole
+/// bookmarks `Model`, only the threads are recovered from them. Their
+/// overviews are also those of the unversioned index.
+#[derive(Deserialize)]
+struct BookmarksV0 {
+    overview: Vec<OverviewV0>,
+}
+
+#[derive(Deserialize)]
+struct OverviewV0 {
+    title: String,
+    url: String,
+    author: String,
+    replies: String,
+}
+
+/// The comments that were loaded along with the threads are dropped, they're
+/// fetched again when a thread is opened.
+fn migrate_v0(old: BookmarksV0) -> BookmarksV1 {
+    let threads = old
+        .overview
+        .into_iter()
+        .map(|o| BookmarkV1 {
+            title: o.title,
+            // Used to be absolute
+            url: resource_path(&o.url),
+            author: o.author,
+            replies: o.replies,
+        })
+        .collect();
+    return BookmarksV1 { threads };
+}
+
+/// Bookmarks had neither folder nor tags.
+fn migrate_v1(old: BookmarksV1) -> BookmarksV2 {
+    let threads = old
+        .threads
+        .into_iter()
+        .map(|b| BookmarkV2 {
+            title: b.title,
+            url: b.url,
+            author: b.author,
+            replies: b.replies,
+            folder: None,
+            tags: vec![],
+        })
+        .collect();
+    return BookmarksV2 { threads };
+}
+
+/// Comments had never been counted.
+fn migrate_v2(old: BookmarksV2) -> BookmarksV3 {
+    let threads = old
+        .threads
+        .into_iter()
+        .map(|b| BookmarkV3 {
+            title: b.title,
+            url: b.url,
+            author: b.author,
+            replies: b.replies,
+            folder: b.folder,
+            tags: b.tags,
+            comments: None,
+            seen: 0,
+        })
+        .collect();
+    return BookmarksV3 { threads };
+}
+
+/// Threads were opened from their first comment.
+fn migrate_v3(old: BookmarksV3) -> BookmarksV4 {
+    let threads = old
+        .threads
+        .into_iter()
+        .map(|b| BookmarkV4 {
+            title: b.title,
+            url: b.url,
+            author: b.author,
+            replies: b.replies,
+            folder: b.folder,
+            tags: b.tags,
+            comments: b.comments,
+            seen: b.seen,
+            selected_comment: 0,
+            viewer_scroll: 0,
+        })
+        .collect();
+    return BookmarksV4 { threads };
+}
+
+/// Reply counts were kept as the text shown by the forum.
+fn migrate_v4(old: BookmarksV4) -> BookmarksV5 {
+    let threads = old
+        .threads
+        .into_iter()
+        .map(|b| BookmarkV5 {
+            title: b.title,
+            url: b.url,
+            author: b.author,
+            replies: parse_count(b.replies.as_str()),
+            folder: b.folder,
+            tags: b.tags,
+            comments: b.comments,
+            seen: b.seen,
+            selected_comment: b.selected_comment,
+            viewer_scroll: b.viewer_scroll,
+        })
+        .collect();
+    return BookmarksV5 { threads };
+}
+
+/// Categories and activity dates weren't kept.
+fn migrate_v5(old: BookmarksV5) -> BookmarksV6 {
+    let threads = old
+        .threads
+        .into_iter()
+        .map(|b| BookmarkV6 {
+            title: b.title,
+            url: b.url,
+            author: b.author,
+            replies: b.replies,
+            folder: b.folder,
+            tags: b.tags,
+            comments: b.comments,
+            seen: b.seen,
+            selected_comment: b.selected_comment,
+            viewer_scroll: b.viewer_scroll,
+            category: None,
+            last_activity: None,
+        })
+        .collect();
+    return BookmarksV6 { threads };
+}
+
+fn to_stored(bookmarks: &Bookmarks) -> BookmarksV6 {
+    let threads = bookmarks
+        .entries()
+        .iter()
+        .map(|b| (b, bookmarks.place(b)))
+        .map(|(b, (selected_comment, viewer_scroll))| BookmarkV6 {
+            title: b.thread.title.clone(),
+            url: b.thread.url.clone(),
+            author: b.thread.author.clone(),
+            replies: b.thread.replies,
+            folder: b.folder.clone(),
+            tags: b.tags.clone(),
+            comments: b.comments,
+            seen: b.seen,
+            selected_comment,
+            viewer_scroll,
+            category: b.thread.category.clone(),
+            last_activity: b.thread.last_activity,
+        })
+        .collect();
+    return BookmarksV6 { threads };
+}
+
+fn from_stored(stored: BookmarksV6) -> Bookmarks {
+    let entries = stored
+        .threads
+        .into_iter()
+        .map(|b| Bookmark {
+            thread: ThreadOverview {
+                title: b.title,
+                url: b.url,
+                author: b.author,
+                replies: b.replies,
+                category: b.category,
+                last_activity: b.last_activity,
+            },
+            folder: b.folder,
+            tags: b.tags,
+            comments: b.comments,
+            seen: b.seen,
+            selected_comment: b.selected_comment,
+            viewer_scroll: b.viewer_scroll,
+        })
+        .collect();
+    return Bookmarks::new(entries);
+}
+
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    XzDecoder::new(bytes).read_to_end(&mut data)?;
+    return Ok(data);
+}
+
+/// Prefixes `payload` with `magic` and `version` as a little endian `u16`.
+fn encode_versioned(magic: &[u8], version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::from(magic);
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.extend_from_slice(payload);
+    return bytes;
+}
+
+/// Splits what `encode_versioned` wrote into the version and the payload,
+/// `None` when `bytes` don't start with `magic`: the file predates the
+/// versioning of its format.
+fn decode_versioned<'a>(bytes: &'a [u8], magic: &[u8]) -> Result<Option<(u16, &'a [u8])>, Error> {
+    let Some(rest) = bytes.strip_prefix(magic) else {
+        return Ok(None);
+    };
+    let [low, high, payload @ ..] = rest else {
+        return Err(Error::Storage(String::from("Truncated file header")));
+    };
+    return match u16::from_le_bytes([*low, *high]) {
+        0 => Err(Error::Storage(String::from(
+            "File is corrupt, its format version is 0",
+        ))),
+        version => Ok(Some((version, payload))),
+    };
+}
+
+pub fn save_bookmarks(path: &Path, bookmarks: &Bookmarks) -> Result<(), Error> {
+    let mut compressor = XzEncoder::new(Vec::new(), 9);
+    compressor.write_all(&serde_cbor::to_vec(&to_stored(bookmarks))?)?;
+    let payload = compressor.finish()?;
+    return write_atomic(path, &encode_versioned(MAGIC, VERSION, &payload));
+}
+
+fn read_bookmarks(path: &Path) -> Result<Bookmarks, Error> {
+    let bytes = fs::read(path)?;
+    let Some((version, payload)) = decode_versioned(&bytes, MAGIC)? else {
+        let old = serde_cbor::from_slice(&decompress(&bytes)?)?;
+        return Ok(from_stored(migrate_v5(migrate_v4(migrate_v3(migrate_v2(
+            migrate_v1(migrate_v0(old)),
+        ))))));
+    };
+    let payload = decompress(payload)?;
+    let stored = match version {
+        1 => migrate_v5(migrate_v4(migrate_v3(migrate_v2(migrate_v1(
+            serde_cbor::from_slice(&payload)?,
+        ))))),
+        2 => migrate_v5(migrate_v4(migrate_v3(migrate_v2(serde_cbor::from_slice(
+            &payload,
+        )?)))),
+        3 => migrate_v5(migrate_v4(migrate_v3(serde_cbor::from_slice(&payload)?))),
+        4 => migrate_v5(migrate_v4(serde_cbor::from_slice(&payload)?)),
+        5 => migrate_v5(serde_cbor::from_slice(&payload)?),
+        6 => serde_cbor::from_slice(&payload)?,
+        version => {
+            return Err(Error::UnsupportedVersion {
+                file: "Bookmarks",
+                version,
+            })
+        }
+    };
+    return Ok(from_stored(stored));
+}
+
+/// Falls back to the backup when the bookmarks file is unreadable or
+/// corrupt, returns `None` if neither exists. A file from a newer client is
+/// an error rather than a reason to go back to older bookmarks.
+pub fn load_bookmarks(path: &Path) -> Result<Option<Bookmarks>, Error> {
+    let backup = backup_path(path);
+    if !path.exists() && !backup.exists() {
+        return Ok(None);
+    }
+    return match read_bookmarks(path) {
+        Ok(bookmarks) => Ok(Some(bookmarks)),
+        Err(e @ Error::UnsupportedVersion { .. }) => Err(e),
+        Err(e) if backup.exists() => read_bookmarks(&backup).map(Some).map_err(|_| e),
+        Err(e) => Err(e),
+    };
+}
+
+/// Identifies a read markers file, followed by the format version as a
+/// little endian `u16` and the CBOR payload.
+const READS_MAGIC: &[u8] = b"OXPHRD";
+const READS_VERSION: u16 = 1;
+
+pub fn save_reads(path: &Path, reads: &ReadMarkers) -> Result<(), Error> {
+    let payload = serde_cbor::to_vec(reads)?;
+    return write_atomic(
+        path,
+        &encode_versioned(READS_MAGIC, READS_VERSION, &payload),
+    );
+}
+
+fn read_reads(path: &Path) -> Result<ReadMarkers, Error> {
+    let bytes = fs::read(path)?;
+    return match decode_versioned(&bytes, READS_MAGIC)? {
+        // Written before the format was versioned, the payload is the same
+        None => Ok(serde_cbor::from_slice(&bytes)?),
+        Some((1, payload)) => Ok(serde_cbor::from_slice(payload)?),
+        Some((version, _)) => Err(Error::UnsupportedVersion {
+            file: "Read markers",
+            version,
+        }),
+    };
+}
+
+/// Same fallback as the bookmarks, nothing has been read if neither exists.
+pub fn load_reads(path: &Path) -> Result<ReadMarkers, Error> {
+    let backup = backup_path(path);
+    if !path.exists() && !backup.exists() {
+        return Ok(ReadMarkers::default());
+    }
+    return match read_reads(path) {
+        Ok(reads) => Ok(reads),
+        Err(e @ Error::UnsupportedVersion { .. }) => Err(e),
+        Err(e) if backup.exists() => read_reads(&backup).map_err(|_| e),
+        Err(e) => Err(e),
+    };
+}
+
+/// Identifies an index file, followed by the format version as a little
+/// endian `u16` and the CBOR payload.
+const INDEX_MAGIC: &[u8] = b"OXPHIX";
+const INDEX_VERSION: u16 = 2;
+
+/// Indexes written before the file was versioned kept the reply counts as
+/// the text shown by the forum.
+#[derive(Deserialize)]
+struct IndexV1 {
+    threads: Vec<IndexedThreadV1>,
+}
+
+#[derive(Deserialize)]
+struct IndexedThreadV1 {
+    thread: OverviewV0,
+    comments: Vec<IndexedComment>,
+}
+
+fn migrate_index_v1(old: IndexV1) -> SearchIndex {
+    let threads = old
+        .threads
+        .into_iter()
+        .map(|t| IndexedThread {
+            thread: ThreadOverview {
+                title: t.thread.title,
+                url: t.thread.url,
+                author: t.thread.author,
+                replies: parse_count(t.thread.replies.as_str()),
+                category: None,
+                last_activity: None,
+            },
+            comments: t.comments,
+        })
+        .collect();
+    return SearchIndex::new(threads);
+}
+
+pub fn save_index(path: &Path, index: &SearchIndex) -> Result<(), Error> {
+    let payload = serde_cbor::to_vec(index)?;
+    return write_atomic(
+        path,
+        &encode_versioned(INDEX_MAGIC, INDEX_VERSION, &payload),
+    );
+}
+
+fn read_index(path: &Path) -> Result<SearchIndex, Error> {
+    let bytes = fs::read(path)?;
+    let Some((version, payload)) = decode_versioned(&bytes, INDEX_MAGIC)? else {
+        // Some unversioned indexes already have counted replies
+        if let Ok(mut index) = serde_cbor::from_slice::<SearchIndex>(&bytes) {
+            index.rebuild();
+            return Ok(index);
+        }
+        return Ok(migrate_index_v1(serde_cbor::from_slice(&bytes)?));
+    };
+    return match version {
+        2 => {
+            let mut index: SearchIndex = serde_cbor::from_slice(payload)?;
+            index.rebuild();
+            Ok(index)
+        }
+        version => Err(Error::UnsupportedVersion {
+            file: "Index",
+            version,
+        }),
+    };
+}
+
+/// Falls back to the backup, then to an empty index when neither is
+/// readable.
+pub fn load_index(path: &Path) -> SearchIndex {
+    return read_index(path)
+        .or_else(|_| read_index(&backup_path(path)))
+        .unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use chrono::TimeZone;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::index::Query;
+
+    /// Empty directory unique to the test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("oxi-phil-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    fn compress(bytes: &[u8]) -> Vec<u8> {
+        let mut compressor = XzEncoder::new(Vec::new(), 6);
+        compressor.write_all(bytes).unwrap();
+        return compressor.finish().unwrap();
+    }
+
+    #[derive(Serialize)]
+    struct LegacyOverview {
+        title: &'static str,
+        url: &'static str,
+        author: &'static str,
+        replies: &'static str,
+    }
+
+    fn legacy_overview() -> LegacyOverview {
+        return LegacyOverview {
+            title: "Free will",
+            url: "https://thephilosophyforum.com/discussion/42/free-will",
+            author: "Ann",
+            replies: "1.2K",
+        };
+    }
+
+    #[test]
+    fn migrations_chain_from_v0() {
+        let old = BookmarksV0 {
+            overview: vec![OverviewV0 {
+                title: String::from("Free will"),
+                url: String::from("https://thephilosophyforum.com/discussion/42/free-will"),
+                author: String::from("Ann"),
+                replies: String::from("1,234"),
+            }],
+        };
+        let new = migrate_v5(migrate_v4(migrate_v3(migrate_v2(migrate_v1(migrate_v0(
+            old,
+        ))))));
+        assert_eq!(new.threads.len(), 1);
+        let b = &new.threads[0];
+        assert_eq!(b.title, "Free will");
+        assert_eq!(b.url, "discussion/42/free-will");
+        assert_eq!(b.author, "Ann");
+        assert_eq!(b.replies, Some(1234));
+        assert_eq!(b.folder, None);
+        assert!(b.tags.is_empty());
+        assert_eq!(b.comments, None);
+        assert_eq!((b.seen, b.selected_comment, b.viewer_scroll), (0, 0, 0));
+        assert_eq!((&b.category, b.last_activity), (&None, None));
+    }
+
+    #[test]
+    fn reads_unversioned_bookmarks() {
+        #[derive(Serialize)]
+        struct LegacyModel {
+            overview: Vec<LegacyOverview>,
+            selected_thread: u16,
+        }
+        let model = LegacyModel {
+            overview: vec![legacy_overview()],
+            selected_thread: 0,
+        };
+        let path = test_dir("unversioned").join("bookmarks.xz");
+        fs::write(&path, compress(&serde_cbor::to_vec(&model).unwrap())).unwrap();
+
+        let bookmarks = read_bookmarks(&path).unwrap();
+        let thread = &bookmarks.entries()[0].thread;
+        assert_eq!(thread.url, "discussion/42/free-will");
+        assert_eq!(thread.replies, Some(1200));
+    }
+
+    #[test]
+    fn bookmarks_survive_a_round_trip() {
+        let mut bookmark = Bookmark::new(ThreadOverview {
+            title: String::from("Free will"),
+            url: String::from("discussion/42/free-will"),
+            author: String::from("Ann"),
+            replies: Some(3),
+            category: Some(String::from("Ethics")),
+            last_activity: Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).single(),
+        });
+        bookmark.folder = Some(String::from("Ethics"));
+        bookmark.tags = vec![String::from("todo")];
+        bookmark.comments = Some(4);
+        bookmark.seen = 2;
+        let path = test_dir("round-trip").join("bookmarks.xz");
+        save_bookmarks(&path, &Bookmarks::new(vec![bookmark])).unwrap();
+
+        let bookmarks = read_bookmarks(&path).unwrap();
+        let b = &bookmarks.entries()[0];
+        assert_eq!(b.thread.url, "discussion/42/free-will");
+        assert_eq!(b.thread.replies, Some(3));
+        assert_eq!(b.folder.as_deref(), Some("Ethics"));
+        assert_eq!(b.tags, ["todo"]);
+        assert_eq!((b.comments, b.seen), (Some(4), 2));
+        assert_eq!(b.thread.category.as_deref(), Some("Ethics"));
+        assert_eq!(
+            b.thread.last_activity,
+            Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 0).single()
+        );
+    }
+
+    #[test]
+    fn rejects_version_0_as_corrupt() {
+        let mut bytes = Vec::from(MAGIC);
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend(compress(&[]));
+        let path = test_dir("version-0").join("bookmarks.xz");
+        fs::write(&path, bytes).unwrap();
+
+        let error = read_bookmarks(&path).err().unwrap();
+        assert!(error.to_string().contains("corrupt"));
+    }
+
+    #[test]
+    fn newer_bookmarks_are_not_replaced_by_the_backup() {
+        let path = test_dir("newer").join("bookmarks.xz");
+        // The second save keeps the first one as the backup
+        save_bookmarks(&path, &Bookmarks::default()).unwrap();
+        save_bookmarks(&path, &Bookmarks::default()).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+
+        let error = load_bookmarks(&path).err().unwrap();
+        assert!(
+            matches!(error, Error::UnsupportedVersion { version, .. } if version == VERSION + 1)
+        );
+    }
+
+    #[test]
+    fn newer_read_markers_are_an_error() {
+        let path = test_dir("newer-reads").join("read.cbor");
+        let payload = serde_cbor::to_vec(&ReadMarkers::default()).unwrap();
+        fs::write(
+            &path,
+            encode_versioned(READS_MAGIC, READS_VERSION + 1, &payload),
+        )
+        .unwrap();
+        fs::write(
+            backup_path(&path),
+            encode_versioned(READS_MAGIC, 1, &payload),
+        )
+        .unwrap();
+
+        let error = load_reads(&path).err().unwrap();
+        assert!(matches!(
+            error,
+            Error::UnsupportedVersion { version: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn truncated_headers_are_corrupt() {
+        assert!(decode_versioned(b"OXPHRD\x01", READS_MAGIC).is_err());
+        assert!(matches!(decode_versioned(b"{}", READS_MAGIC), Ok(None)));
+        let decoded = decode_versioned(b"OXPHRD\x02\x01ab", READS_MAGIC).unwrap();
+        assert_eq!(decoded, Some((258, &b"ab"[..])));
+    }
+
+    #[test]
+    fn migrates_unversioned_index() {
+        #[derive(Serialize)]
+        struct LegacyThread {
+            thread: LegacyOverview,
+            comments: Vec<IndexedComment>,
+        }
+        #[derive(Serialize)]
+        struct LegacyIndex {
+            threads: Vec<LegacyThread>,
+        }
+        let old = LegacyIndex {
+            threads: vec![LegacyThread {
+                thread: legacy_overview(),
+                comments: vec![IndexedComment {
+                    author: String::from("Ann"),
+                    text: String::from("free will is an illusion"),
+                }],
+            }],
+        };
+        let path = test_dir("index").join("index.cbor");
+        fs::write(&path, serde_cbor::to_vec(&old).unwrap()).unwrap();
+
+        let index = load_index(&path);
+        let hits = index.search(&Query::parse("illusion"));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].thread.replies, Some(1200));
+
+        save_index(&path, &index).unwrap();
+        assert_eq!(load_index(&path).search(&Query::parse("will")).len(), 1);
+    }
+}
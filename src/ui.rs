@@ -1,15 +1,35 @@
-use std::{error::Error, io::Stdout};
+use std::{
+    io::{self, stdout, Stdout},
+    panic,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use crossterm::{
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{Color, Style, Stylize},
-    text::Line,
-    widgets::{Block, Borders, List, ListState, Paragraph, Row, Table, TableState, Wrap},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{
+        Block, Borders, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState, Wrap,
+    },
     Frame, Terminal,
 };
 
-use crate::{model::Model, thread::ThreadData};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    bookmarks::Bookmark,
+    cache::now,
+    dates::{age, local, relative},
+    model::{Model, TabState},
+    overview::{SortMode, Source, ThreadOverview},
+    search::{comment_matches, highlight, match_style},
+    thread::ThreadData,
+};
 
 fn generate_layout(frame: &Frame) -> (Rect, Rect, Rect, Rect) {
     let root = Layout::default()
@@ -28,20 +48,21 @@ fn generate_layout(frame: &Frame) -> (Rect, Rect, Rect, Rect) {
 }
 
 pub fn view(model: &Model, frame: &mut Frame) {
-    let (overview, comments, viewer, _) = generate_layout(frame);
-    let thread = if model.overview.is_empty() {
-        None
-    } else {
-        Some(model.data.data.get(model.selected_thread as usize).unwrap())
-    };
+    let (overview, comments, viewer, info) = generate_layout(frame);
+    let list = model.list();
+    let thread = list.selected_data();
 
     render_overview(model, frame, overview);
     render_comment_list(thread, model, frame, comments);
     render_viwer(thread, model, frame, viewer);
+    render_status(model, frame, info);
+    render_categories(model, frame);
+    render_index_results(model, frame);
+    render_error(model, frame);
 }
 
 fn render_viwer(thread: Option<&ThreadData>, model: &Model, frame: &mut Frame, area: Rect) {
-    fn generate_paragraph<'a>(text: Vec<Line<'a>>, title: &'a str, offset: u16) -> Paragraph<'a> {
+    fn generate_paragraph(text: Vec<Line<'_>>, title: String, offset: u16) -> Paragraph<'_> {
         Paragraph::new(text)
             .block(
                 Block::new()
@@ -55,15 +76,30 @@ fn render_viwer(thread: Option<&ThreadData>, model: &Model, frame: &mut Frame, a
             .scroll((offset, 0))
     }
 
-    let (text, title, offset) = if thread.is_some() {
-        let t = thread.unwrap();
-        let comment = t
-            .comments
-            .get(model.data.selected_comment as usize)
-            .unwrap();
-        (comment.get_lines(), t.title.as_str(), model.viewer_scroll)
+    let list = model.list();
+    let (text, title, offset) = if let Some(t) = thread {
+        let comment = t.comments.get(t.selected_comment as usize);
+        let text = match comment {
+            Some(comment) => {
+                let lines = comment.get_lines(area.width.saturating_sub(2));
+                match model.comment_search.as_ref() {
+                    Some(query) => highlight(lines, query),
+                    None => lines,
+                }
+            }
+            None if list.selected().is_some_and(|over| model.is_loading(over)) => {
+                vec![Line::raw("Loading...")]
+            }
+            None => vec![],
+        };
+        let date = comment
+            .and_then(|c| c.date)
+            .map(|d| format!(" - {}", local(d)))
+            .unwrap_or_default();
+        let title = format!("{}{}{}", t.title, date, cached_label(t.cached_at));
+        (text, title, t.viewer_scroll)
     } else {
-        (vec![], "", 0)
+        (vec![], String::new(), 0)
     };
 
     let parag = generate_paragraph(text, title, offset);
@@ -71,20 +107,40 @@ fn render_viwer(thread: Option<&ThreadData>, model: &Model, frame: &mut Frame, a
 }
 
 fn render_comment_list(thread: Option<&ThreadData>, model: &Model, frame: &mut Frame, area: Rect) {
+    let list = model.list();
+    let url = list.selected().map_or("", |o| o.url.as_str());
+    let query = model.comment_search.as_deref();
+    let mut matches = 0;
     let rows = if let Some(t) = thread {
         t.comments
             .iter()
-            .map(|x| Row::new(vec![x.author.as_str(), x.date.as_str()]))
+            .enumerate()
+            .map(|(i, x)| {
+                let date = x.date.map(relative).unwrap_or_default();
+                let row = Row::new(vec![x.author.clone(), date]);
+                if query.is_some_and(|q| comment_matches(x, q)) {
+                    matches += 1;
+                    row.style(match_style())
+                } else if model.reads.is_unread(url, i, x.date) {
+                    row.style(unread_style())
+                } else {
+                    row
+                }
+            })
             .collect()
     } else {
         vec![]
     };
+    let title = match query {
+        Some(q) => format!("Comments (/{}: {} matches)", q, matches),
+        None => String::from("Comments"),
+    };
 
     let widths = [Constraint::Percentage(30), Constraint::Percentage(50)];
     let table = Table::new(rows, widths)
         .block(
             Block::default()
-                .title("Comments")
+                .title(title)
                 .style(Style::default().fg(Color::Red))
                 .borders(Borders::ALL),
         )
@@ -92,46 +148,390 @@ fn render_comment_list(thread: Option<&ThreadData>, model: &Model, frame: &mut F
         .highlight_symbol(">>");
 
     let mut state = TableState::default();
-    let s = if thread.is_some() {
-        Some(model.data.selected_comment.into())
-    } else {
-        None
-    };
+    let s = thread
+        .filter(|t| !t.comments.is_empty())
+        .map(|t| (t.selected_comment as usize).min(t.comments.len() - 1));
     state.select(s);
     frame.render_stateful_widget(table, area, &mut state);
 }
 
 fn render_overview(model: &Model, frame: &mut Frame, area: Rect) {
-    let threads_list = List::new(model.overview.iter().map(|item| item.title.as_str()))
+    let list = model.list();
+    let name = match (model.tab, &list.source) {
+        (TabState::Bookmarks, _) => String::from("Bookmarks"),
+        (_, Source::Category(category)) => format!("Overview: {}", category.name),
+        (_, Source::Search(query)) => format!("Search: {}", query),
+        (_, Source::Index(query)) => format!("Read threads: {}", query),
+        (TabState::Search, Source::Discussions) => String::from("Search"),
+        (TabState::Home, Source::Discussions) => String::from("Overview"),
+    };
+    let filter = match (model.tab, model.bookmarks.filter.as_ref()) {
+        (TabState::Bookmarks, Some(f)) => format!(" ({})", f),
+        _ => String::new(),
+    };
+    let thread_filter = match list.filter.as_ref() {
+        Some(f) => format!(" [{}]", f),
+        None => String::new(),
+    };
+    let sort = match list.sort {
+        SortMode::Listed => String::new(),
+        s => format!(" by {}", s.label()),
+    };
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Min(3), Constraint::Length(DETAILS_HEIGHT)])
+        .split(area);
+    // Borders and highlight symbol
+    let width = areas[0].width.saturating_sub(4);
+    let columns = columns(width);
+    let title_width = columns
+        .iter()
+        .fold(width, |left, &(_, w)| left.saturating_sub(w + 1));
+
+    let shown = list.shown();
+    let rows = shown.iter().map(|&i| {
+        let item = &list.overview[i];
+        let label = match model.tab {
+            TabState::Bookmarks => bookmark_label(item, model.bookmarks.get(item.url.as_str())),
+            _ => item.title.clone(),
+        };
+        let mut cells = vec![label];
+        for (column, _) in columns.iter() {
+            cells.push(match column {
+                Column::Replies => item.replies.map(|r| r.to_string()).unwrap_or_default(),
+                Column::Activity => list.last_activity(i).map(relative).unwrap_or_default(),
+                Column::Author => item.author.clone(),
+                Column::Category => category(item, &list.source).unwrap_or_default().to_string(),
+            });
+        }
+        if model.has_unread(i) {
+            Row::new(cells).style(unread_style())
+        } else {
+            Row::new(cells)
+        }
+    });
+    let header = std::iter::once("Title").chain(columns.iter().map(|(c, _)| c.label()));
+    let widths = std::iter::once(Constraint::Length(title_width))
+        .chain(columns.iter().map(|&(_, w)| Constraint::Length(w)));
+    let table = Table::new(rows, widths)
+        .header(Row::new(header).style(Style::default().bold()))
         .block(
             Block::default()
-                .title("Overview")
+                .title(format!(
+                    "{}{}{}{}{}",
+                    name,
+                    filter,
+                    thread_filter,
+                    sort,
+                    cached_label(list.overview_cached_at)
+                ))
                 .borders(Borders::ALL)
                 .style(Style::default().fg(Color::Red)),
         )
         .style(Style::default().fg(Color::White))
         .highlight_style(Style::default().bg(Color::LightBlue))
         .highlight_symbol(">>");
-    let mut state = ListState::default();
-    let s = if model.overview.is_empty() {
-        None
-    } else {
-        Some(model.selected_thread.into())
+    let mut state = TableState::default();
+    state.select(
+        shown
+            .iter()
+            .position(|&n| n == list.selected_thread as usize),
+    );
+    frame.render_stateful_widget(table, areas[0], &mut state);
+    render_details(model, frame, areas[1]);
+}
+
+/// What is known of the selected thread, before it's even loaded.
+fn render_details(model: &Model, frame: &mut Frame, area: Rect) {
+    let list = model.list();
+    let mut text = vec![];
+    if let (Some(over), Some(t)) = (list.selected(), list.selected_data()) {
+        let mut facts = vec![];
+        if !over.author.is_empty() {
+            facts.push(format!("by {}", over.author));
+        }
+        if let Some(replies) = over.replies {
+            facts.push(format!("{} replies", replies));
+        }
+        if let Some(category) = category(over, &list.source) {
+            facts.push(format!("in {}", category));
+        }
+        if model.bookmarks.contains(over.url.as_str()) {
+            facts.push(String::from("bookmarked"));
+        }
+        text.push(Line::styled(over.title.clone(), Style::default().bold()));
+        text.push(Line::raw(facts.join(", ")));
+        if let Some(date) = list.last_activity(list.selected_thread as usize) {
+            let activity = format!("Last activity {} ({})", local(date), relative(date));
+            text.push(Line::raw(activity));
+        }
+        text.push(Line::raw(match (t.comment_page, t.complete) {
+            (0, _) => String::from("Not loaded yet"),
+            (_, true) => format!("{} comments", t.comments.len()),
+            (_, false) => format!("{} comments loaded", t.comments.len()),
+        }));
+    }
+    let parag = Paragraph::new(text)
+        .block(
+            Block::default()
+                .title("Details")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Red)),
+        )
+        .style(Style::new().white())
+        .wrap(Wrap { trim: false });
+    frame.render_widget(parag, area);
+}
+
+/// Columns shown next to the titles, the last ones are dropped first when
+/// the overview is too narrow.
+#[derive(Clone, Copy)]
+enum Column {
+    Replies,
+    Activity,
+    Author,
+    Category,
+}
+
+impl Column {
+    fn label(self) -> &'static str {
+        return match self {
+            Column::Replies => "Replies",
+            Column::Activity => "Active",
+            Column::Author => "Author",
+            Column::Category => "Category",
+        };
+    }
+}
+
+const COLUMNS: [(Column, u16); 4] = [
+    (Column::Replies, 7),
+    (Column::Activity, 10),
+    (Column::Author, 16),
+    (Column::Category, 16),
+];
+const MIN_TITLE_WIDTH: u16 = 30;
+const DETAILS_HEIGHT: u16 = 7;
+
+/// Columns fitting in `width` along with the titles.
+fn columns(width: u16) -> Vec<(Column, u16)> {
+    let mut left = width.saturating_sub(MIN_TITLE_WIDTH);
+    return COLUMNS
+        .into_iter()
+        .take_while(|&(_, w)| {
+            // Columns are separated by a space
+            let fits = left > w;
+            left = left.saturating_sub(w + 1);
+            fits
+        })
+        .collect();
+}
+
+/// Threads listed from a category all belong to it.
+fn category<'a>(thread: &'a ThreadOverview, source: &'a Source) -> Option<&'a str> {
+    return match (thread.category.as_deref(), source) {
+        (Some(name), _) => Some(name),
+        (None, Source::Category(c)) => Some(c.name.as_str()),
+        (None, _) => None,
     };
-    state.select(s);
-    frame.render_stateful_widget(threads_list, area, &mut state);
 }
 
-pub fn print_info(
-    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    text: &str,
-) -> Result<(), Box<dyn Error>> {
-    terminal.draw(|frame| {
-        let (_, _, _, info_area) = generate_layout(frame);
-        let parag = Paragraph::new(text)
+fn unread_style() -> Style {
+    return Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+}
+
+/// Title followed by the folder and tags of the bookmark.
+fn bookmark_label(thread: &ThreadOverview, bookmark: Option<&Bookmark>) -> String {
+    let mut label = thread.title.clone();
+    let Some(b) = bookmark else {
+        return label;
+    };
+    if let Some(folder) = b.folder.as_ref() {
+        label.push_str(format!(" [{}]", folder).as_str());
+    }
+    for tag in b.tags.iter() {
+        label.push_str(format!(" #{}", tag).as_str());
+    }
+    if b.new_comments() > 0 {
+        label.push_str(format!(" ({} new)", b.new_comments()).as_str());
+    }
+    return label;
+}
+
+fn render_status(model: &Model, frame: &mut Frame, area: Rect) {
+    if let Some(prompt) = model.prompt.as_ref() {
+        let text = format!("{}: {}", prompt.kind.label(), prompt.input);
+        let cursor = area.x + (text.width() as u16).min(area.width.saturating_sub(1));
+        frame.render_widget(Paragraph::new(text), area);
+        frame.set_cursor(cursor, area.y);
+        return;
+    }
+    if let Some((_, request)) = model.pending.first() {
+        let parag = Paragraph::new(format!("{} {}", spinner(), request.description()))
             .style(Style::default().yellow())
             .alignment(Alignment::Left);
-        frame.render_widget(parag, info_area);
-    })?;
-    return Ok(());
+        frame.render_widget(parag, area);
+    }
+}
+
+fn render_categories(model: &Model, frame: &mut Frame) {
+    let Some(selected) = model.category_picker else {
+        return;
+    };
+    let area = centered(frame.size(), 50, 60);
+    let names = model.categories.iter().map(|c| c.name.as_str());
+    let items: Vec<&str> = std::iter::once("All discussions").chain(names).collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title("Categories")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Red)),
+        )
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().bg(Color::LightBlue))
+        .highlight_symbol(">>");
+    let mut state = ListState::default();
+    state.select(Some(selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_index_results(model: &Model, frame: &mut Frame) {
+    let Some(results) = model.index_results.as_ref() else {
+        return;
+    };
+    let area = centered(frame.size(), 80, 70);
+    let items: Vec<ListItem> = results
+        .hits
+        .iter()
+        .map(|hit| {
+            ListItem::new(vec![
+                Line::from(vec![
+                    Span::styled(hit.thread.title.clone(), Style::default().bold()),
+                    Span::raw(format!(" #{} by {}", hit.comment + 1, hit.author)),
+                ]),
+                Line::styled(hit.snippet.clone(), Style::default().fg(Color::DarkGray)),
+            ])
+        })
+        .collect();
+    let title = match results.hits.len() {
+        0 => format!("Read threads: {} (no match)", results.query),
+        n => format!("Read threads: {} ({} matches)", results.query, n),
+    };
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Red)),
+        )
+        .style(Style::default().fg(Color::White))
+        .highlight_style(Style::default().bg(Color::LightBlue))
+        .highlight_symbol(">>");
+    let mut state = ListState::default();
+    state.select(Some(results.selected).filter(|_| !results.hits.is_empty()));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_error(model: &Model, frame: &mut Frame) {
+    let Some(error) = model.error.as_ref() else {
+        return;
+    };
+    let area = centered(frame.size(), 60, 30);
+    let hint = if error.retry.is_some() {
+        "r: retry, Esc: dismiss"
+    } else {
+        "Esc: dismiss"
+    };
+    let text = vec![
+        Line::raw(error.message.as_str()),
+        Line::raw(""),
+        Line::styled(hint, Style::default().fg(Color::DarkGray)),
+    ];
+    let parag = Paragraph::new(text)
+        .block(
+            Block::default()
+                .title("Error")
+                .borders(Borders::ALL)
+                .style(Style::default().fg(Color::Red)),
+        )
+        .style(Style::new().white())
+        .wrap(Wrap { trim: false });
+    frame.render_widget(Clear, area);
+    frame.render_widget(parag, area);
+}
+
+fn centered(area: Rect, width_percent: u16, height_percent: u16) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![
+            Constraint::Percentage((100 - height_percent) / 2),
+            Constraint::Percentage(height_percent),
+            Constraint::Percentage((100 - height_percent) / 2),
+        ])
+        .split(area);
+    return Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Percentage((100 - width_percent) / 2),
+            Constraint::Percentage(width_percent),
+            Constraint::Percentage((100 - width_percent) / 2),
+        ])
+        .split(vertical[1])[1];
+}
+
+fn cached_label(cached_at: Option<u64>) -> String {
+    let Some(at) = cached_at else {
+        return String::new();
+    };
+    return format!(" (cached {} ago)", age(now().saturating_sub(at)));
+}
+
+fn spinner() -> char {
+    const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    return FRAMES[(millis / 100) as usize % FRAMES.len()];
+}
+
+/// Puts the terminal back into its normal state once dropped, whichever way
+/// the application exits.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<(Self, Terminal<CrosstermBackend<Stdout>>)> {
+        stdout().execute(EnterAlternateScreen)?;
+        let guard = TerminalGuard;
+        enable_raw_mode()?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+        terminal.clear()?;
+        return Ok((guard, terminal));
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = stdout().execute(LeaveAlternateScreen);
+}
+
+/// Restores the terminal before the panic message gets printed, the release
+/// profile aborts so no destructor would run.
+pub fn install_panic_hook() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        hook(info);
+    }));
 }